@@ -0,0 +1,277 @@
+//! Static checks over the stage graph of a valid game, for `advgame check`: stages that can't be reached,
+//! stages the game can't be finished from, conditions that can never be met and stats nobody reads.
+//! Like `diagnostics::validate`, works on a game that wasn't post processed yet.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::diagnostics::{self, Diagnostic};
+use crate::game_components::*;
+use crate::game_state::GameState;
+use crate::ids::{Id, Symbols};
+use crate::markup;
+use crate::parsing::{read_game, LoadError};
+use crate::template;
+
+/// The values a stat can take, as far as the effects touching it tell.
+/// Effects of items count even if no option uses the item, so this may allow more than the game does.
+struct Bounds {
+    min: i64,
+    max: i64,
+    /// Every value the stat can take, as long as it's only ever set to exact values.
+    values: Option<Vec<i64>>,
+}
+
+impl Bounds {
+    fn new(default: StatValue) -> Bounds {
+        Bounds { min: default as i64, max: default as i64, values: Some(vec![default as i64]) }
+    }
+
+    fn change(&mut self, by: i64) {
+        if by > 0 {
+            self.max = i64::MAX;
+        } else if by < 0 {
+            self.min = i64::MIN;
+        }
+        if by != 0 {
+            self.values = None;
+        }
+    }
+
+    fn set(&mut self, value: i64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        if let Some(values) = &mut self.values {
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+    }
+
+    fn allows(&self, condition: &Condition) -> bool {
+        match *condition {
+            Condition::Always => true,
+            Condition::IfStatHigher { higher_than, .. } => self.max > higher_than as i64,
+            Condition::IfStatLower { lower_than, .. } => self.min < lower_than as i64,
+            Condition::IfStatExact { value, .. } => match &self.values {
+                Some(values) => values.contains(&(value as i64)),
+                None => self.min <= value as i64 && value as i64 <= self.max,
+            },
+        }
+    }
+
+    /// Why a condition can't be met, like `"Attack" never goes above 5`.
+    fn describe(&self, stat: &str) -> String {
+        match &self.values {
+            Some(values) => {
+                let values: Vec<String> = values.iter().map(i64::to_string).collect();
+                format!("\"{}\" can only be {}", stat, values.join(", "))
+            }
+            None if self.min == i64::MIN => format!("\"{}\" never goes above {}", stat, self.max),
+            None => format!("\"{}\" never goes below {}", stat, self.min),
+        }
+    }
+}
+
+fn condition_stat(condition: &Condition) -> Option<&Id> {
+    match condition {
+        Condition::Always => None,
+        Condition::IfStatHigher { stat_id, .. } | Condition::IfStatLower { stat_id, .. } |
+        Condition::IfStatExact { stat_id, .. } => Some(stat_id),
+    }
+}
+
+struct Analyzer<'a> {
+    game: &'a GameState,
+    symbols: &'a Symbols,
+    /// Bounds of stats by position.
+    bounds: HashMap<usize, Bounds>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Analyzer<'a> {
+    fn apply(&mut self, effect: &Effect) {
+        let stats = &self.symbols.stats;
+        let (stat, change) = match effect {
+            Effect::SetStatHigher { stat_id, to_add } => (stats.resolve(stat_id), Some(*to_add as i64)),
+            Effect::SetStatLower { stat_id, to_subtract } => (stats.resolve(stat_id), Some(-(*to_subtract as i64))),
+            Effect::SetStatExact { stat_id, new_value } => {
+                if let Some(bounds) = stats.resolve(stat_id).ok().and_then(|stat| self.bounds.get_mut(&stat)) {
+                    bounds.set(*new_value as i64);
+                }
+                return;
+            }
+            _ => return,
+        };
+        if let (Some(bounds), Some(change)) = (stat.ok().and_then(|stat| self.bounds.get_mut(&stat)), change) {
+            bounds.change(change);
+        }
+    }
+
+    /// Works out the bounds of every stat from all effects in the game.
+    fn collect_bounds(&mut self) {
+        let game = self.game;
+        self.bounds = game.stats.iter().enumerate().map(|(i, stat)| (i, Bounds::new(stat.value))).collect();
+        for option in game.stages.iter().flat_map(|stage| stage.options.iter()) {
+            self.apply(&option.effect);
+        }
+        for item in &game.items {
+            match &item.effect {
+                ItemEffect::NoEffect => {}
+                ItemEffect::Consumable { on_consume } => self.apply(on_consume),
+                ItemEffect::Equippable { when_equipped, when_unequipped, .. } => {
+                    self.apply(when_equipped);
+                    self.apply(when_unequipped);
+                }
+            }
+        }
+    }
+
+    /// Whether the condition can ever be met. Reports it if it can't.
+    fn check_condition(&mut self, condition: &Condition, pointer: String, subject: String) -> bool {
+        let stat = match condition_stat(condition) {
+            Some(stat_id) => self.symbols.stats.resolve(stat_id).ok(),
+            None => return true,
+        };
+        let bounds = stat.and_then(|stat| self.bounds.get(&stat).map(|bounds| (&self.game.stats[stat], bounds)));
+        match bounds {
+            Some((stat, bounds)) if !bounds.allows(condition) => {
+                let message = format!("The condition can never be true, {}.", bounds.describe(&markup::strip(&stat.name)));
+                self.diagnostics.push(Diagnostic::warning(pointer, subject, message));
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// The stages every stage leads to, by position, through options that can ever be picked.
+    fn edges(&mut self) -> Vec<Vec<usize>> {
+        let game = self.game;
+        let mut edges = vec![Vec::new(); game.stages.len()];
+        for (i, stage) in game.stages.iter().enumerate() {
+            for (j, option) in stage.options.iter().enumerate() {
+                let pointer = format!("/stages/{}/options/{}/condition", i, j);
+                let subject = format!("{}, option {}", diagnostics::subject("stage", &stage.name), j + 1);
+                if self.check_condition(&option.condition, pointer, subject) {
+                    edges[i].extend(self.symbols.stages.resolve(&option.target_stage).ok());
+                }
+            }
+        }
+        edges
+    }
+
+    fn check_stages(&mut self) {
+        let game = self.game;
+        let edges = self.edges();
+        let mut reverse = vec![Vec::new(); edges.len()];
+        for (from, targets) in edges.iter().enumerate() {
+            for &to in targets {
+                reverse[to].push(from);
+            }
+        }
+        let stages = &self.symbols.stages;
+        let (entry, exit) = match (stages.resolve(&game.entry_stage), stages.resolve(&game.exit_stage)) {
+            (Ok(entry), Ok(exit)) => (entry, exit),
+            _ => return,
+        };
+        let reachable = visit(&edges, &[entry]);
+        let finishing = visit(&reverse, &[exit]);
+
+        for (i, stage) in game.stages.iter().enumerate() {
+            let subject = diagnostics::subject("stage", &stage.name);
+            if !reachable.contains(&i) {
+                self.diagnostics.push(Diagnostic::warning(format!("/stages/{}", i), subject,
+                                                          "The stage can't be reached from the entry stage.".to_string()));
+                continue;
+            }
+            if finishing.contains(&i) {
+                continue;
+            }
+            // Only report where the player gets stuck, not every stage they can wander to afterwards.
+            let entered_from_outside = i == entry ||
+                reverse[i].iter().any(|from| reachable.contains(from) && finishing.contains(from));
+            if entered_from_outside {
+                let mut stuck: Vec<usize> = visit(&edges, &[i]).into_iter().collect();
+                stuck.sort_unstable();
+                let names: Vec<String> = stuck.iter().map(|&j| format!("\"{}\"", markup::strip(&game.stages[j].name))).collect();
+                let message = format!("The game can't be finished once the player gets here, \
+                                       they can only go through {} from then on.", names.join(", "));
+                self.diagnostics.push(Diagnostic::warning(format!("/stages/{}", i), subject, message));
+            }
+        }
+    }
+
+    fn check_achievements(&mut self) {
+        for (i, achievement) in self.game.achievements.iter().enumerate() {
+            if let AchievementTrigger::Live { condition } | AchievementTrigger::OnFinish { condition } = &achievement.trigger {
+                let pointer = format!("/achievements/{}/trigger/{}/condition", i,
+                                      diagnostics::variant_name(&achievement.trigger));
+                self.check_condition(condition, pointer, diagnostics::subject("achievement", &achievement.name));
+            }
+        }
+    }
+
+    /// Stats are read by conditions, and by placeholders in stage and option texts.
+    fn check_stats(&mut self) {
+        let game = self.game;
+        let mut read: HashSet<usize> = HashSet::new();
+        let conditions = game.stages.iter().flat_map(|stage| stage.options.iter().map(|option| &option.condition))
+            .chain(game.achievements.iter().filter_map(|achievement| match &achievement.trigger {
+                AchievementTrigger::OnEffect => None,
+                AchievementTrigger::Live { condition } | AchievementTrigger::OnFinish { condition } => Some(condition),
+            }));
+        let symbols = self.symbols;
+        read.extend(conditions.filter_map(condition_stat).filter_map(|stat_id| symbols.stats.resolve(stat_id).ok()));
+        let texts = game.stages.iter().flat_map(|stage| stage.text.values()
+            .chain(stage.options.iter().flat_map(|option| option.text.values())))
+            .flatten();
+        for text in texts {
+            let stats = template::compile(text, game).map(|template| template.stats()).unwrap_or_default();
+            read.extend(stats);
+        }
+        for (i, stat) in game.stats.iter().enumerate() {
+            if !read.contains(&i) {
+                self.diagnostics.push(Diagnostic::warning(
+                    format!("/stats/{}", i), diagnostics::subject("stat", &stat.name),
+                    "The stat is never read, by a condition or in a text.".to_string(),
+                ));
+            }
+        }
+    }
+}
+
+/// All positions reachable from `start` by following `edges`.
+fn visit(edges: &[Vec<usize>], start: &[usize]) -> HashSet<usize> {
+    let mut seen: HashSet<usize> = start.iter().cloned().collect();
+    let mut queue: VecDeque<usize> = start.iter().cloned().collect();
+    while let Some(stage) = queue.pop_front() {
+        for &next in &edges[stage] {
+            if seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    seen
+}
+
+/// Finds problems `diagnostics::validate` doesn't look for. The game must be valid.
+pub fn analyze(game: &GameState, symbols: &Symbols) -> Vec<Diagnostic> {
+    let mut analyzer = Analyzer { game, symbols, bounds: HashMap::new(), diagnostics: Vec::new() };
+    analyzer.collect_bounds();
+    analyzer.check_stages();
+    analyzer.check_achievements();
+    analyzer.check_stats();
+    analyzer.diagnostics
+}
+
+/// Reads a game and reports every problem in it, both those that keep it from being played and
+/// those `analyze` finds, located in the files they come from.
+pub fn check_game(path: &str) -> Result<Vec<Diagnostic>, LoadError> {
+    let (game, sources, mut found) = read_game(path)?;
+    let symbols = Symbols::new(&game);
+    diagnostics::add_new(&mut found, diagnostics::validate(&game, &symbols));
+    if !diagnostics::has_errors(&found) {
+        found.extend(analyze(&game, &symbols));
+    }
+    diagnostics::locate(&mut found, &sources);
+    Ok(found)
+}
@@ -0,0 +1,36 @@
+use std::fs;
+
+use crate::profile;
+
+/// User settings shared by all games, read from `config.json` in the data directory.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Config {
+    #[serde(default = "Config::default_autosave_slots")]
+    pub autosave_slots: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            autosave_slots: Config::default_autosave_slots(),
+        }
+    }
+}
+
+impl Config {
+    fn default_autosave_slots() -> usize { 3 }
+
+    /// Missing or broken config files fall back to the defaults.
+    pub fn load() -> Config {
+        profile::data_dir()
+            .and_then(|dir| fs::read_to_string(dir.join("config.json")).ok())
+            .and_then(|json| match serde_json::from_str::<Config>(&json) {
+                Ok(config) => Some(config),
+                Err(err) => {
+                    dprintln!("Ignoring a broken config file: {}", err);
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+}
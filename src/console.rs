@@ -1,3 +1,5 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::fmt;
 use std::sync::Mutex;
 
@@ -6,34 +8,150 @@ use ncurses::*;
 use crate::debug::DEBUG;
 use crate::game_components::Stage;
 use crate::game_state::GameState;
+use crate::markup::{self, Color, Span, Style};
 use crate::misc;
+use crate::profile::Profile;
+
+/// The smallest terminal the game is laid out in, see `Console::get_ch`.
+const MIN_ROWS: i32 = 12;
+const MIN_COLS: i32 = 60;
 
 pub struct Console {
-    row_count: i32,
-    col_count: i32,
+    /// The size of the terminal, updated when it's resized.
+    row_count: Cell<i32>,
+    col_count: Cell<i32>,
+    /// Actions used instead of the keyboard while replaying a recording.
+    replay: RefCell<Option<VecDeque<Action>>>,
+    /// Actions taken so far, if recording.
+    recording: RefCell<Option<Vec<Action>>>,
+    /// How many rows of the stage are scrolled past, see `print_stage`.
+    scroll: Cell<usize>,
+    /// The stage and option the stage was last shown with, to scroll to the option once it changes.
+    shown: Cell<Option<(usize, usize)>>,
+    /// Whether the key hints at the bottom offer undo, see `GameState::allow_undo`.
+    undo: Cell<bool>,
+}
+extern "C" {
+    fn waddnwstr(window: WINDOW, text: *const libc::wchar_t, length: libc::c_int) -> libc::c_int;
+}
+
+/// Writes text to the window from where its cursor is, through the wide-character API
+/// so that curses knows how many columns each character takes.
+fn add_text(window: WINDOW, text: &str) {
+    // Curses reads up to the terminating null even when given the length.
+    let wide: Vec<libc::wchar_t> = text.chars().map(|c| c as libc::wchar_t).chain(std::iter::once(0)).collect();
+    unsafe {
+        waddnwstr(window, wide.as_ptr(), wide.len() as libc::c_int - 1);
+    }
+}
+
+/// Writes text to the window at the given row and column.
+fn print_at(window: WINDOW, y: i32, x: i32, text: &str) {
+    wmove(window, y, x);
+    add_text(window, text);
 }
+
 lazy_static! {
     pub static ref DEBUG_LOG: Mutex<String> = Mutex::new(String::new());
 }
 
 impl Console {
     pub fn new() -> Console {
+        // The game may have been read from standard input, so keys have to come from the terminal.
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) == 0 {
+                let tty = libc::open(b"/dev/tty\0".as_ptr() as *const libc::c_char, libc::O_RDONLY);
+                if tty >= 0 {
+                    libc::dup2(tty, libc::STDIN_FILENO);
+                    libc::close(tty);
+                }
+            }
+        }
+        // Curses measures characters as the locale says, which has to be set before it starts.
+        unsafe {
+            libc::setlocale(libc::LC_CTYPE, b"\0".as_ptr() as *const libc::c_char);
+        }
         initscr();
-        let mut x = 0;
-        let mut y = 0;
-        getmaxyx(stdscr(), &mut y, &mut x);
         raw();
         noecho();
         keypad(stdscr(), true);
         curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
-        return Console {
-            row_count: y,
-            col_count: x,
+        if has_colors() {
+            start_color();
+            use_default_colors();
+            for (i, color) in Color::ALL.iter().enumerate() {
+                init_pair(i as i16 + 1, *color as i16, -1);
+            }
+        }
+        let console = Console {
+            row_count: Cell::new(0),
+            col_count: Cell::new(0),
+            replay: RefCell::new(None),
+            recording: RefCell::new(None),
+            scroll: Cell::new(0),
+            shown: Cell::new(None),
+            undo: Cell::new(false),
         };
+        console.update_size();
+        console
+    }
+
+    /// Reads the size of the terminal again.
+    fn update_size(&self) {
+        let mut x = 0;
+        let mut y = 0;
+        getmaxyx(stdscr(), &mut y, &mut x);
+        self.row_count.set(y);
+        self.col_count.set(x);
+    }
+
+    fn is_too_small(&self) -> bool {
+        self.row_count.get() < MIN_ROWS || self.col_count.get() < MIN_COLS
+    }
+
+    pub fn set_undo(&self, undo: bool) {
+        self.undo.set(undo);
+    }
+
+    /// The key hints shown at the bottom of every screen, on as many rows as they need.
+    fn footer(&self) -> Vec<String> {
+        let hints = ["y/enter to confirm", "n to decline", "backspace to undo", "PgUp/PgDn to scroll", "v to save",
+                     "t for a transcript", "q to exit"];
+        let width = (self.col_count.get() as usize).saturating_sub(4);
+        let mut rows: Vec<String> = Vec::new();
+        for hint in hints.iter().filter(|hint| self.undo.get() || !hint.ends_with("undo")) {
+            match rows.last_mut() {
+                Some(row) if misc::str_width(row) + 2 + misc::str_width(hint) <= width => {
+                    row.push_str(", ");
+                    row.push_str(hint);
+                }
+                _ => rows.push(hint.to_string()),
+            }
+        }
+        rows.into_iter().map(|row| format!("| {} |", row)).collect()
+    }
+
+    /// From now on, actions are taken from `actions` instead of the keyboard.
+    /// Once they run out, the console keeps returning `Action::Quit`.
+    pub fn start_replay(&self, actions: Vec<Action>) {
+        *self.replay.borrow_mut() = Some(actions.into_iter().collect());
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.borrow().is_some()
+    }
+
+    pub fn start_recording(&self) {
+        *self.recording.borrow_mut() = Some(Vec::new());
+    }
+
+    /// Returns the actions recorded since `start_recording`.
+    pub fn take_recording(&self) -> Option<Vec<Action>> {
+        self.recording.borrow_mut().take()
     }
 
     pub fn print_center_offset(&self, msg: &str, offset: i32) -> &Console {
-        mvprintw(self.row_count / 2 + offset, (self.col_count - msg.len() as i32) / 2, &msg);
+        print_at(stdscr(), self.row_count.get() / 2 + offset, self.left_align(misc::str_width(msg) as i32).max(0), msg);
         refresh();
         &self
     }
@@ -41,33 +159,82 @@ impl Console {
     pub fn print_center(&self, msg: &str) -> &Console { self.print_center_offset(msg, 0) }
 
     pub fn print_top_offset(&self, msg: &str, offset: i32) {
-        mvprintw(offset, (self.col_count - msg.len() as i32) / 2, &msg);
+        print_at(stdscr(), offset, self.left_align(misc::str_width(msg) as i32).max(0), msg);
         refresh();
     }
 
     #[allow(unused)]
     pub fn print_top(&self, msg: &str) { self.print_top_offset(msg, 0); }
 
+    /// Like `print_top_offset`, for text with markup, like names.
+    fn print_top_marked(&self, text: &str, style: Style, offset: i32) {
+        let spans = markup::spans(text, style);
+        mv(offset, self.left_align(misc::str_width(&markup::plain(&spans)) as i32).max(0));
+        self.print_spans(&spans);
+        refresh();
+    }
+
     pub fn left_align(&self, width: i32) -> i32 {
-        (self.col_count - width) / 2
+        (self.col_count.get() - width) / 2
+    }
+
+    /// The attributes text of the style is shown with. Colors are left out on terminals without them.
+    fn attributes(style: Style) -> attr_t {
+        let mut attributes = A_NORMAL();
+        if style.bold {
+            attributes |= A_BOLD();
+        }
+        if style.underline {
+            attributes |= A_UNDERLINE();
+        }
+        match style.color {
+            Some(color) if has_colors() => attributes | COLOR_PAIR(color as i16 + 1),
+            _ => attributes,
+        }
+    }
+
+    /// Prints styled text from where the cursor is.
+    fn print_spans(&self, spans: &[Span]) {
+        for span in spans {
+            let attributes = Console::attributes(span.style);
+            attr_on(attributes);
+            add_text(stdscr(), &span.text);
+            attr_off(attributes);
+        }
     }
 
+    /// The rows of the stage between its name and the key hints, from `top`.
+    fn stage_rows(&self) -> (i32, usize) {
+        (3, (self.row_count.get() - 4 - self.footer().len() as i32).max(1) as usize)
+    }
+
+    /// Scrolls the stage by the given number of screens, up if negative.
+    pub fn scroll_pages(&self, pages: i32) {
+        let (_, height) = self.stage_rows();
+        let step = (height.max(2) - 1) as i64 * pages as i64;
+        self.scroll.set((self.scroll.get() as i64 + step).max(0) as usize);
+    }
+
+    /// Shows the stage name, then its text and options wrapped to the width of the terminal.
+    /// What doesn't fit is scrolled to with PgUp and PgDn, and the selected option is scrolled into view
+    /// whenever it changes.
     pub fn print_stage(&self, stage: &Stage, game: &GameState) {
         // Stage name is in bold.
         dprintln!("Printing stage {:?}", stage);
-        attr_on(A_BOLD());
-        self.print_top_offset(&stage.name, 1);
-        attr_off(A_BOLD());
+        self.print_top_marked(&stage.name, Style { bold: true, ..Style::default() }, 1);
 
-        let mut current_line_nr = 4;
-        let max_width = misc::max_str_len(&stage.text);
-        for line in stage.text.iter() {
-            let line = game.parse_format_text(line);
-            mvprintw(current_line_nr, self.left_align(max_width as i32), &line);
-            current_line_nr += 1;
+        let available = (self.col_count.get() - 2).max(1) as usize;
+        // Rows to show, with where they start.
+        let mut rows: Vec<(i32, Vec<Span>)> = vec![(0, Vec::new())];
+        let lines: Vec<Vec<Span>> = stage.text.iter().map(|line| game.format_text(line)).collect();
+        let text_width = lines.iter().map(|line| misc::str_width(&markup::plain(line))).max().unwrap_or(0).min(available);
+        for line in &lines {
+            for row in markup::wrap(line, text_width) {
+                rows.push((self.left_align(text_width as i32), row));
+            }
         }
-        current_line_nr += 2;
-
+        rows.push((0, Vec::new()));
+        rows.push((0, Vec::new()));
 
         // To print options evenly, we need the maximum line width.
         // As we can have lots of options, we need to figure out how many digits
@@ -78,38 +245,282 @@ impl Console {
         const DOT_AND_SPACE: usize = 2;
         let max_number_width = game.visible_options(stage).count().to_string().len();
         let cont_offset = max_number_width + DOT_AND_SPACE + ARROW.len();
-        let max_width = game.visible_options(stage)
-            .map(|option| { misc::max_str_len(&option.text) })
+        let option_width = game.visible_options(stage)
+            .flat_map(|option| option.text.iter().map(|line| misc::str_width(&game.parse_format_text(line))))
             .max()
             .unwrap_or(0)
-            + 2 * cont_offset;
+            .min(available.saturating_sub(cont_offset).max(1));
+        let left = self.left_align((option_width + 2 * cont_offset).min(available) as i32);
 
+        let mut selected = 0..0;
         let mut display_index = 0;
         for (internal_index, option) in stage.options.iter().enumerate() {
             if !game.is_filled(option) {
                 continue;
             }
             display_index += 1;
-            let arrow = if internal_index == stage.current_option - 1 {
+            let arrow = if stage.current_option.checked_sub(1) == Some(internal_index) {
                 ARROW.to_string()
             } else { " ".repeat(ARROW.len()) };
-            let row_text = if option.text.len() == 0 { "" } else { &option.text[0] };
-            let row = game.parse_format_text(&format!("{}{}. {}", arrow, display_index, row_text));
+            let first = rows.len();
+            for (i, line) in option.text.iter().enumerate() {
+                for row in markup::wrap(&game.format_text(line), option_width) {
+                    rows.push((left + cont_offset as i32, row));
+                }
+                if i == 0 {
+                    let number = format!("{}{}. ", arrow, display_index);
+                    let number = Span { text: format!("{:>width$}", number, width = cont_offset), style: Style::default() };
+                    match rows.get_mut(first) {
+                        Some(row) => *row = (left, std::iter::once(number).chain(row.1.drain(..)).collect()),
+                        None => rows.push((left, vec![number])),
+                    }
+                }
+            }
+            dprintln!("Printing option {}, first row text is {:?}", display_index, rows.get(first));
+            if stage.current_option.checked_sub(1) == Some(internal_index) {
+                selected = first..rows.len();
+            }
+            rows.push((0, Vec::new()));
+        }
+        rows.pop();
+
+        let (top, height) = self.stage_rows();
+        let mut scroll = self.scroll.get();
+        let shown = Some((game.current_stage, stage.current_option));
+        let previous = self.shown.replace(shown);
+        if previous != shown {
+            if previous.map(|(index, _)| index) != Some(game.current_stage) {
+                scroll = 0;
+            }
+            if selected.end > scroll + height {
+                scroll = selected.end - height;
+            }
+            scroll = scroll.min(selected.start);
+        }
+        scroll = scroll.min(rows.len().saturating_sub(height));
+        self.scroll.set(scroll);
+        for (i, (x, row)) in rows.iter().skip(scroll).take(height).enumerate() {
+            mv(top + i as i32, *x);
+            self.print_spans(row);
+        }
+        attr_on(A_DIM());
+        if scroll > 0 {
+            self.print_top_offset("^ PgUp for more ^", top - 1);
+        }
+        if scroll + height < rows.len() {
+            self.print_top_offset("v PgDn for more v", top + height as i32);
+        }
+        attr_off(A_DIM());
+    }
 
-            dprintln!("Printing option {}, first row text is {}", display_index, row);
-            mvprintw(current_line_nr, self.left_align(max_width as i32), &row);
-            for line in option.text[1..].iter() {
-                let line = " ".repeat(cont_offset) + line;
+    /// The name of the game and what its metadata tells about it, with the prompt lines below.
+    pub fn print_title_screen(&self, game: &GameState, prompt: &[&str]) {
+        let metadata = &game.metadata;
+        let bold = Style { bold: true, ..Style::default() };
+        self.print_top_marked(game.get_name(), bold, 2);
+        let mut current_line_nr = 3;
+        if let Some(author) = &metadata.author {
+            self.print_top_offset(&format!("by {}", author), current_line_nr);
+            current_line_nr += 1;
+        }
+        let details: Vec<String> = metadata.version.iter().map(|version| format!("version {}", version))
+            .chain(metadata.language.iter().cloned())
+            .chain(metadata.license.iter().cloned())
+            .collect();
+        if !details.is_empty() {
+            attr_on(A_DIM());
+            self.print_top_offset(&details.join(" | "), current_line_nr);
+            attr_off(A_DIM());
+            current_line_nr += 1;
+        }
+        if let Some(description) = &metadata.description {
+            current_line_nr += 1;
+            for line in description.lines() {
+                self.print_top_marked(line, Style::default(), current_line_nr);
                 current_line_nr += 1;
-                mvprintw(current_line_nr, self.left_align(max_width as i32), &line);
             }
-            current_line_nr += 2;
         }
+        if !metadata.content_warnings.is_empty() {
+            current_line_nr += 1;
+            self.print_top_marked(&format!("Content warnings: {}", metadata.content_warnings.join(", ")),
+                                  bold, current_line_nr);
+            current_line_nr += 1;
+        }
+        current_line_nr = (current_line_nr + 2).max(self.row_count.get() / 2);
+        for line in prompt {
+            self.print_top_offset(line, current_line_nr);
+            current_line_nr += 1;
+        }
+    }
+
+    /// The intro of the game, in the middle of the screen.
+    pub fn print_intro(&self, game: &GameState) {
+        let intro: Vec<Vec<Span>> = game.metadata.intro.iter().map(|line| game.format_text(line)).collect();
+        let max_width = intro.iter().map(|line| misc::str_width(&markup::plain(line))).max().unwrap_or(0);
+        let top = (self.row_count.get() - intro.len() as i32) / 2 - 1;
+        for (i, line) in intro.iter().enumerate() {
+            mv(top + i as i32, self.left_align(max_width as i32));
+            self.print_spans(line);
+        }
+        refresh();
+    }
+
+    pub fn print_achievements(&self, game: &GameState, profile: &Profile) {
+        attr_on(A_BOLD());
+        self.print_top_offset("Achievements", 1);
+        attr_off(A_BOLD());
+
+        if game.achievements.is_empty() {
+            self.print_center("This game has no achievements.");
+            return;
+        }
+        let unlocked_count = game.achievements.iter()
+            .filter(|achievement| profile.is_unlocked(achievement.name.original()))
+            .count();
+        self.print_top_offset(&format!("Unlocked {} of {}", unlocked_count, game.achievements.len()), 2);
+
+        const UNLOCKED: &str = "[x] ";
+        const LOCKED: &str = "[ ] ";
+        let lines: Vec<Vec<Span>> = game.achievements.iter().map(|achievement| {
+            let mut line = markup::spans(&achievement.name, Style::default());
+            if !achievement.description.is_empty() {
+                markup::push(&mut line, " - ", Style::default());
+                for span in markup::spans(&achievement.description, Style::default()) {
+                    markup::push(&mut line, &span.text, span.style);
+                }
+            }
+            line
+        }).collect();
+        let max_width = lines.iter().map(|line| misc::str_width(&markup::plain(line))).max().unwrap_or(0) +
+            UNLOCKED.len();
+        for (i, (achievement, line)) in game.achievements.iter().zip(lines.iter()).enumerate() {
+            let unlocked = profile.is_unlocked(achievement.name.original());
+            if !unlocked {
+                attr_on(A_DIM());
+            }
+            let marker = if unlocked { UNLOCKED } else { LOCKED };
+            print_at(stdscr(), 4 + i as i32, self.left_align(max_width as i32), marker);
+            self.print_spans(line);
+            attr_off(A_DIM());
+        }
+        refresh();
     }
 
+    /// Draws a framed box with the given lines in the middle of the screen
+    /// and waits for a key press.
+    pub fn show_popup(&self, title: &str, lines: &[String]) {
+        while self.draw_popup(title, lines) == Some(KEY_RESIZE) {
+            self.clear();
+        }
+    }
+
+    /// Draws the popup and returns the key that closed it.
+    fn draw_popup(&self, title: &str, lines: &[String]) -> Option<i32> {
+        const PADDING: i32 = 2;
+        let content_width = misc::max_str_len(&lines.to_vec()).max(misc::str_width(title)) as i32;
+        let width = content_width + 2 * PADDING + 2;
+        let height = lines.len() as i32 + 4;
+        let top = (self.row_count.get() - height) / 2;
+        let left = (self.col_count.get() - width) / 2;
+
+        let window = newwin(height, width, top, left);
+        box_(window, 0, 0);
+        wattr_on(window, A_BOLD());
+        print_at(window, 1, (width - misc::str_width(title) as i32) / 2, title);
+        wattr_off(window, A_BOLD());
+        for (i, line) in lines.iter().enumerate() {
+            print_at(window, i as i32 + 3, (width - misc::str_width(line) as i32) / 2, line);
+        }
+        wrefresh(window);
+        let key = self.get_ch();
+        delwin(window);
+        touchwin(stdscr());
+        refresh();
+        key
+    }
+
+    /// Shows what `draw` prints until a key is pressed, printing it again when the terminal is resized.
+    pub fn show_until_key<F: Fn()>(&self, draw: F) {
+        loop {
+            self.clear();
+            draw();
+            if self.get_ch() != Some(KEY_RESIZE) {
+                return;
+            }
+        }
+    }
+
+    /// Lets the user choose one of the entries. Returns `None` if the user backs out.
+    pub fn pick(&self, title: &str, entries: &[String]) -> Option<usize> {
+        if entries.is_empty() {
+            return None;
+        }
+        const ARROW: &str = "--> ";
+        let max_width = misc::max_str_len(&entries.to_vec()) + 2 * ARROW.len();
+        let mut selected = 0;
+        loop {
+            self.clear();
+            attr_on(A_BOLD());
+            self.print_top_offset(title, 1);
+            attr_off(A_BOLD());
+            for (i, entry) in entries.iter().enumerate() {
+                let arrow = if i == selected { ARROW.to_string() } else { " ".repeat(ARROW.len()) };
+                print_at(stdscr(), 4 + 2 * i as i32, self.left_align(max_width as i32), &(arrow + entry));
+            }
+            refresh();
+            match self.get_action() {
+                Action::Up => selected = (selected + entries.len() - 1) % entries.len(),
+                Action::Down => selected = (selected + 1) % entries.len(),
+                Action::Number(num) if 0 < num && num <= entries.len() => return Some(num - 1),
+                Action::Confirm => return Some(selected),
+                Action::Cancel | Action::Quit => return None,
+                _ => {}
+            }
+        }
+    }
+
+    /// Asks the user to type a line of text. Returns `None` if nothing was typed.
+    pub fn read_line(&self, prompt: &str, max_len: i32) -> Option<String> {
+        if self.is_replaying() {
+            return None;
+        }
+        self.clear();
+        self.print_center(prompt);
+        mv(self.row_count.get() / 2 + 2, self.left_align(max_len));
+        echo();
+        curs_set(CURSOR_VISIBILITY::CURSOR_VISIBLE);
+        let mut line = String::new();
+        getnstr(&mut line, max_len);
+        curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+        noecho();
+        let line = line.trim().to_string();
+        if line.is_empty() { None } else { Some(line) }
+    }
+
+    /// Waits for a key press. Returns `KEY_RESIZE` once the terminal is resized, so that the screen
+    /// can be drawn again. While the terminal is too small, only a notice is shown and only
+    /// q and resizes are returned.
     pub fn get_ch(&self) -> Option<i32> {
-        Some(getch())
-        // maybe some stuff needs to be handled in the future?
+        // Key presses that don't become actions are skipped when replaying.
+        if self.is_replaying() {
+            return Some(0);
+        }
+        loop {
+            if self.is_too_small() {
+                clear();
+                self.print_center("The terminal is too small!");
+                self.print_center_offset(&format!("Make it at least {}x{}", MIN_COLS, MIN_ROWS), 1);
+                self.print_center_offset("or press q to quit.", 2);
+            }
+            let ch = getch();
+            if ch == KEY_RESIZE {
+                self.update_size();
+                return Some(ch);
+            }
+            if !self.is_too_small() || ch == 'q' as i32 || ch == 'Q' as i32 {
+                return Some(ch);
+            }
+        }
     }
 
     fn interpret_ch(&self, ch: i32) -> Action {
@@ -123,6 +534,9 @@ impl Console {
                 'W' | 'w' => Action::Up,
                 'S' | 's' => Action::Down,
                 'N' | 'n' => Action::Cancel,
+                'A' | 'a' => Action::Achievements,
+                'V' | 'v' => Action::Save,
+                'T' | 't' => Action::Transcript,
                 _ => Action::Unimplemented
             }
         } else {
@@ -131,6 +545,9 @@ impl Console {
                 KEY_BACKSPACE => Action::Cancel,
                 KEY_UP => Action::Up,
                 KEY_DOWN => Action::Down,
+                KEY_PPAGE => Action::PageUp,
+                KEY_NPAGE => Action::PageDown,
+                KEY_RESIZE => Action::Resize,
                 KEY_END if DEBUG => Action::Debug,
                 _ => Action::Unimplemented
             }
@@ -138,17 +555,31 @@ impl Console {
     }
 
     pub fn get_action(&self) -> Action {
-        let c = match self.get_ch() {
-            Some(c) => c,
-            None => return Action::Quit
+        let replayed = self.replay.borrow_mut().as_mut()
+            .map(|actions| actions.pop_front().unwrap_or(Action::Quit));
+        let action = match replayed {
+            Some(action) => action,
+            None => match self.get_ch() {
+                Some(c) => self.interpret_ch(c),
+                None => Action::Quit
+            }
         };
-        self.interpret_ch(c)
+        if let Some(recording) = self.recording.borrow_mut().as_mut() {
+            match action {
+                Action::Unimplemented | Action::Debug | Action::Resize => {}
+                _ => recording.push(action.clone()),
+            }
+        }
+        action
     }
 
     pub fn clear(&self) {
         clear();
-        let msg = "| y/enter to confirm, n to decline, q to exit |";
-        mvprintw(self.row_count - 1, (self.col_count - msg.len() as i32) / 2, &msg);
+        let footer = self.footer();
+        for (i, row) in footer.iter().enumerate() {
+            let y = self.row_count.get() - (footer.len() - i) as i32;
+            print_at(stdscr(), y, self.left_align(misc::str_width(row) as i32), row);
+        }
     }
 }
 
@@ -162,6 +593,8 @@ impl Drop for Console {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
 pub enum Action {
     Confirm,
     Quit,
@@ -171,6 +604,13 @@ pub enum Action {
     Down,
     Number(usize),
     Debug,
+    Achievements,
+    Save,
+    Transcript,
+    PageUp,
+    PageDown,
+    /// The terminal was resized and the screen has to be drawn again.
+    Resize,
 }
 impl fmt::Display for Action {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -185,6 +625,12 @@ impl fmt::Display for Action {
                 Action::Up => "Up",
                 Action::Down => "Down",
                 Action::Debug => "Debug",
+                Action::Achievements => "Achievements",
+                Action::Save => "Save",
+                Action::Transcript => "Transcript",
+                Action::PageUp => "PageUp",
+                Action::PageDown => "PageDown",
+                Action::Resize => "Resize",
                 _ => "This will never be printed."
             })
         }
@@ -0,0 +1,510 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::game_components::*;
+use crate::game_state::GameState;
+use crate::ids::{Id, Symbols, Table};
+use crate::locale::Localized;
+use crate::markup;
+use crate::parsing::GameFormat;
+use crate::template;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The game can't be played.
+    Error,
+    /// The game can be played, but probably not the way the author meant.
+    Warning,
+}
+
+/// A problem found in a game, pointing to where it is in the game file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The file the problem is in, once known, see `locate`.
+    pub file: Option<String>,
+    /// JSON pointer to the value, like `/stages/2/options/0/target_stage`.
+    pub pointer: String,
+    pub line: Option<usize>,
+    /// The stage, option, item or achievement involved, like `stage "Stage 1", option 2`.
+    pub subject: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(pointer: &str, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            file: None,
+            pointer: pointer.to_string(),
+            line: None,
+            subject: String::new(),
+            message,
+        }
+    }
+
+    pub fn warning(pointer: String, subject: String, message: String) -> Diagnostic {
+        Diagnostic { severity: Severity::Warning, file: None, pointer, line: None, subject, message }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: ")?,
+            Severity::Warning => write!(f, "warning: ")?,
+        }
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}:{} ", file, line)?,
+            (Some(file), None) => write!(f, "{} ", file)?,
+            _ => {}
+        }
+        write!(f, "{}", if self.pointer.is_empty() { "/" } else { &self.pointer })?;
+        if !self.subject.is_empty() {
+            write!(f, ", {}", self.subject)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+/// Names what a problem is about, like `stage "Stage 1"`, without the markup of the name.
+pub fn subject(kind: &str, name: &str) -> String {
+    format!("{} \"{}\"", kind, markup::strip(name))
+}
+
+/// Adds the problems a later check found, except those with values that already have one reported.
+pub fn add_new(found: &mut Vec<Diagnostic>, more: Vec<Diagnostic>) {
+    let known: Vec<String> = found.iter().map(|diagnostic| diagnostic.pointer.clone()).collect();
+    found.extend(more.into_iter().filter(|diagnostic| !known.contains(&diagnostic.pointer)));
+}
+
+/// The name serde gives the variant, which is also its key in game files.
+pub fn variant_name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        Ok(serde_json::Value::Object(map)) => map.keys().next().cloned().unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+struct Validator<'a> {
+    game: &'a GameState,
+    symbols: &'a Symbols,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Validator<'a> {
+    fn report(&mut self, severity: Severity, pointer: String, subject: String, message: String) {
+        self.diagnostics.push(Diagnostic { severity, file: None, pointer, line: None, subject, message });
+    }
+
+    fn error(&mut self, pointer: String, subject: String, message: String) {
+        self.report(Severity::Error, pointer, subject, message);
+    }
+
+    /// Reports every id that's used twice.
+    fn check_ids<I: Iterator<Item=(&'a Id, &'a str)>>(&mut self, list: &str, kind: &str, id_field: &str, entries: I) {
+        let mut positions: HashMap<&Id, usize> = HashMap::new();
+        for (i, (id, name)) in entries.enumerate() {
+            match positions.get(id) {
+                Some(first) => {
+                    let message = format!("The id {} is already used by {} number {}.", id, kind, first + 1);
+                    self.error(format!("/{}/{}/{}", list, i, id_field), subject(kind, name), message);
+                }
+                None => {
+                    positions.insert(id, i);
+                }
+            }
+        }
+    }
+
+    /// The position the id stands for, reporting it if there's none.
+    fn check_id(&mut self, table: &Table, id: &Id, pointer: String, subject: &str) -> Option<usize> {
+        match table.resolve(id) {
+            Ok(position) => Some(position),
+            Err(message) => {
+                self.error(pointer, subject.to_string(), message);
+                None
+            }
+        }
+    }
+
+    fn check_condition(&mut self, condition: &Condition, pointer: &str, subject: &str) {
+        match condition {
+            Condition::Always => {}
+            Condition::IfStatHigher { stat_id, .. } | Condition::IfStatLower { stat_id, .. } |
+            Condition::IfStatExact { stat_id, .. } => {
+                let pointer = format!("{}/{}/stat_id", pointer, variant_name(condition));
+                let symbols = self.symbols;
+                self.check_id(&symbols.stats, stat_id, pointer, subject);
+            }
+        }
+    }
+
+    fn check_effect(&mut self, effect: &Effect, pointer: &str, subject: &str) {
+        let pointer = format!("{}/{}", pointer, variant_name(effect));
+        let symbols = self.symbols;
+        match effect {
+            Effect::NoEffect => {}
+            Effect::SetStatHigher { stat_id, .. } | Effect::SetStatLower { stat_id, .. } |
+            Effect::SetStatExact { stat_id, .. } => {
+                self.check_id(&symbols.stats, stat_id, pointer + "/stat_id", subject);
+            }
+            Effect::UseItem { item_id } => {
+                self.check_id(&symbols.items, item_id, pointer + "/item_id", subject);
+            }
+            Effect::UnlockAchievement { achievement_id } => {
+                self.check_id(&symbols.achievements, achievement_id, pointer + "/achievement_id", subject);
+            }
+        }
+    }
+
+    /// Reports placeholders that don't compile, see `template`.
+    fn check_text(&mut self, text: &str, pointer: &str, subject: &str) {
+        if let Err(messages) = template::compile(text, self.game) {
+            for message in messages {
+                self.error(pointer.to_string(), subject.to_string(), message);
+            }
+        }
+    }
+
+    /// Checks every line in every language, `pointer` is where the text is.
+    fn check_lines(&mut self, text: &Localized<Vec<String>>, pointer: &str, subject: &str) {
+        for (locale, lines) in text.entries() {
+            let pointer = if locale.is_empty() { pointer.to_string() } else { format!("{}/{}", pointer, locale) };
+            for (i, line) in lines.iter().enumerate() {
+                self.check_text(line, &format!("{}/{}", pointer, i), subject);
+            }
+        }
+    }
+
+    fn check_texts(&mut self) {
+        let game = self.game;
+        self.check_lines(&game.metadata.intro, "/metadata/intro", "intro");
+        for (i, stage) in game.stages.iter().enumerate() {
+            let subject = subject("stage", &stage.name);
+            self.check_lines(&stage.text, &format!("/stages/{}/text", i), &subject);
+            for (j, option) in stage.options.iter().enumerate() {
+                self.check_lines(&option.text, &format!("/stages/{}/options/{}/text", i, j),
+                                 &format!("{}, option {}", subject, j + 1));
+            }
+        }
+        for (i, item) in game.items.iter().enumerate() {
+            let subject = subject("item", &item.name);
+            for (locale, name) in item.name.entries() {
+                let pointer = if locale.is_empty() { format!("/items/{}/name", i) } else {
+                    format!("/items/{}/name/{}", i, locale)
+                };
+                match template::compile(name, game) {
+                    Ok(name) if name.shows_items() => self.error(pointer, subject.clone(),
+                                                                 "Item names can't show items.".to_string()),
+                    _ => self.check_text(name, &pointer, &subject),
+                }
+            }
+        }
+    }
+
+    fn check_stages(&mut self) {
+        let game = self.game;
+        let symbols = self.symbols;
+        self.check_id(&symbols.stages, &game.entry_stage, "/entry_stage".to_string(), "");
+        let exit = self.check_id(&symbols.stages, &game.exit_stage, "/exit_stage".to_string(), "");
+        for (i, stage) in game.stages.iter().enumerate() {
+            let subject = subject("stage", &stage.name);
+            if stage.options.is_empty() && exit.is_some() && exit != Some(i) {
+                self.error(format!("/stages/{}/options", i), subject.clone(),
+                           "The stage has no options, but it isn't the exit stage.".to_string());
+            }
+            if !stage.options.is_empty() && exit == Some(i) {
+                self.error(format!("/stages/{}/options", i), subject.clone(),
+                           "The exit stage can't have options.".to_string());
+            }
+            for (j, option) in stage.options.iter().enumerate() {
+                let pointer = format!("/stages/{}/options/{}", i, j);
+                let subject = format!("{}, option {}", subject, j + 1);
+                if option.text.is_empty() {
+                    self.error(pointer.clone() + "/text", subject.clone(), "The option has no text.".to_string());
+                }
+                self.check_id(&symbols.stages, &option.target_stage, pointer.clone() + "/target_stage", &subject);
+                self.check_condition(&option.condition, &(pointer.clone() + "/condition"), &subject);
+                self.check_effect(&option.effect, &(pointer + "/effect"), &subject);
+            }
+        }
+    }
+
+    fn check_items(&mut self) {
+        for (i, item) in self.game.items.iter().enumerate() {
+            let subject = subject("item", &item.name);
+            let pointer = format!("/items/{}/effect/{}", i, variant_name(&item.effect));
+            match &item.effect {
+                ItemEffect::NoEffect => {}
+                ItemEffect::Consumable { on_consume } =>
+                    self.check_effect(on_consume, &(pointer + "/on_consume"), &subject),
+                ItemEffect::Equippable { slot, when_equipped, when_unequipped } => {
+                    if !self.game.item_slots.contains(slot) {
+                        self.report(Severity::Warning, pointer.clone() + "/slot", subject.clone(),
+                                    format!("The slot \"{}\" isn't listed in item_slots.", slot));
+                    }
+                    self.check_effect(when_equipped, &(pointer.clone() + "/when_equipped"), &subject);
+                    self.check_effect(when_unequipped, &(pointer + "/when_unequipped"), &subject);
+                }
+            }
+        }
+    }
+
+    fn check_achievements(&mut self) {
+        for (i, achievement) in self.game.achievements.iter().enumerate() {
+            let subject = subject("achievement", &achievement.name);
+            let pointer = format!("/achievements/{}/trigger/{}/condition", i, variant_name(&achievement.trigger));
+            match &achievement.trigger {
+                AchievementTrigger::OnEffect => {}
+                AchievementTrigger::Live { condition } | AchievementTrigger::OnFinish { condition } =>
+                    self.check_condition(condition, &pointer, &subject),
+            }
+        }
+    }
+}
+
+/// Finds every problem that would keep the game from being post processed, plus a few warnings.
+/// Works on a game that wasn't post processed yet, so ids are still the ones from the file,
+/// looked up in `symbols`.
+pub fn validate(game: &GameState, symbols: &Symbols) -> Vec<Diagnostic> {
+    let mut validator = Validator { game, symbols, diagnostics: Vec::new() };
+    validator.check_ids("stats", "stat", "id", game.stats.iter().map(|stat| (&stat.id, &stat.name[..])));
+    validator.check_ids("stages", "stage", "index", game.stages.iter().map(|stage| (&stage.index, &stage.name[..])));
+    validator.check_ids("items", "item", "id", game.items.iter().map(|item| (&item.id, &item.name[..])));
+    validator.check_ids("achievements", "achievement", "id",
+                        game.achievements.iter().map(|a| (&a.id, &a.name[..])));
+    validator.check_stages();
+    validator.check_items();
+    validator.check_achievements();
+    validator.check_texts();
+    validator.diagnostics
+}
+
+/// A file a game was read from, with the part of every list of the merged game it filled.
+pub struct Source {
+    pub name: String,
+    pub text: String,
+    pub format: GameFormat,
+    pub ranges: Vec<(&'static str, Range<usize>)>,
+}
+
+impl Source {
+    pub fn new(name: &str, text: &str, format: GameFormat, game: &GameState, before: &[usize; 4]) -> Source {
+        let after = list_lengths(game);
+        Source {
+            name: name.to_string(),
+            text: text.to_string(),
+            format,
+            ranges: LISTS.iter().enumerate().map(|(i, list)| (*list, before[i]..after[i])).collect(),
+        }
+    }
+}
+
+const LISTS: [&str; 4] = ["stats", "stages", "items", "achievements"];
+
+/// The lengths of the lists included files add to, in the order of `LISTS`.
+pub fn list_lengths(game: &GameState) -> [usize; 4] {
+    [game.stats.len(), game.stages.len(), game.items.len(), game.achievements.len()]
+}
+
+/// Points the diagnostics to the files their values came from, and to lines in JSON files.
+/// The first source is the game file itself.
+pub fn locate(diagnostics: &mut [Diagnostic], sources: &[Source]) {
+    for diagnostic in diagnostics.iter_mut() {
+        let mut segments = diagnostic.pointer.splitn(4, '/').skip(1);
+        let list = segments.next().unwrap_or("");
+        let index = segments.next().and_then(|index| index.parse::<usize>().ok());
+        let found = index.and_then(|index| sources.iter().find_map(|source| {
+            source.ranges.iter().find(|(name, range)| *name == list && range.contains(&index))
+                .map(|(_, range)| (source, index - range.start))
+        }));
+        let source = match found {
+            Some((source, local_index)) => {
+                let rest = segments.next().map_or(String::new(), |rest| format!("/{}", rest));
+                diagnostic.pointer = format!("/{}/{}{}", list, local_index, rest);
+                source
+            }
+            None => match sources.first() {
+                Some(source) => source,
+                None => continue,
+            },
+        };
+        diagnostic.file = Some(source.name.clone());
+        if source.format == GameFormat::Json {
+            diagnostic.line = json_pointer_line(&source.text, &diagnostic.pointer);
+        }
+    }
+}
+
+/// Scans a JSON document for the value a JSON pointer points to, keeping track of lines.
+struct JsonScanner<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    target: &'a str,
+}
+
+impl<'a> JsonScanner<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.chars.peek() {
+            match c {
+                '\n' => self.line += 1,
+                c if c.is_whitespace() => {}
+                _ => return,
+            }
+            self.chars.next();
+        }
+    }
+
+    fn string(&mut self) -> String {
+        let mut text = String::new();
+        self.chars.next();
+        while let Some(c) = self.chars.next() {
+            match c {
+                '"' => break,
+                '\\' => if let Some(escaped) = self.chars.next() {
+                    text.push(escaped);
+                },
+                c => text.push(c),
+            }
+        }
+        text
+    }
+
+    /// Returns the line of the target, if it's this value or inside it.
+    fn value(&mut self, path: &str) -> Option<usize> {
+        self.skip_whitespace();
+        if path == self.target {
+            return Some(self.line);
+        }
+        match self.chars.peek() {
+            Some('{') => {
+                self.chars.next();
+                loop {
+                    self.skip_whitespace();
+                    match self.chars.peek() {
+                        Some('"') => {}
+                        Some(',') => {
+                            self.chars.next();
+                            continue;
+                        }
+                        _ => {
+                            self.chars.next();
+                            return None;
+                        }
+                    }
+                    let key = self.string().replace('~', "~0").replace('/', "~1");
+                    self.skip_whitespace();
+                    self.chars.next();
+                    let found = self.value(&format!("{}/{}", path, key));
+                    if found.is_some() {
+                        return found;
+                    }
+                }
+            }
+            Some('[') => {
+                self.chars.next();
+                let mut index = 0;
+                loop {
+                    self.skip_whitespace();
+                    match self.chars.peek() {
+                        Some(',') => {
+                            self.chars.next();
+                            index += 1;
+                        }
+                        Some(']') | None => {
+                            self.chars.next();
+                            return None;
+                        }
+                        _ => {
+                            let found = self.value(&format!("{}/{}", path, index));
+                            if found.is_some() {
+                                return found;
+                            }
+                        }
+                    }
+                }
+            }
+            Some('"') => {
+                self.string();
+                None
+            }
+            _ => {
+                while let Some(c) = self.chars.peek() {
+                    if *c == ',' || *c == '}' || *c == ']' || c.is_whitespace() {
+                        break;
+                    }
+                    self.chars.next();
+                }
+                None
+            }
+        }
+    }
+}
+
+/// The line of the value a JSON pointer points to, counted from 1.
+pub fn json_pointer_line(text: &str, pointer: &str) -> Option<usize> {
+    JsonScanner { chars: text.chars().peekable(), line: 1, target: pointer }.value("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(stats: serde_json::Value) -> GameState {
+        serde_json::from_value(serde_json::json!({
+            "name": "Test",
+            "stats": stats,
+            "stages": [
+                {"index": 0, "name": "Start", "text": ["${stat.0}"], "options": [{"target_stage": 1, "text": ["Go."]}]},
+                {"index": 1, "name": "End", "text": ["The end."], "options": []},
+            ],
+            "item_slots": [],
+            "items": [],
+            "entry_stage": 0,
+            "exit_stage": 1,
+        })).unwrap()
+    }
+
+    fn errors(game: &GameState) -> Vec<String> {
+        validate(game, &Symbols::new(game)).iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn accepts_a_valid_game() {
+        let game = game(serde_json::json!([{"id": 0, "name": "HP", "default_value": 1}]));
+        assert!(errors(&game).is_empty());
+    }
+
+    #[test]
+    fn reports_ids_used_twice() {
+        let game = game(serde_json::json!([
+            {"id": 0, "name": "HP", "default_value": 1},
+            {"id": 0, "name": "_Mana_", "default_value": 1},
+        ]));
+        assert_eq!(errors(&game), vec!["error: /stats/1/id, stat \"Mana\": The id 0 is already used by stat number 1."
+            .to_string()]);
+    }
+
+    #[test]
+    fn adds_only_problems_with_new_values() {
+        let mut found = vec![Diagnostic::error("/stats/1/id", "The id 0 is already used in \"main.json\".".to_string())];
+        add_new(&mut found, vec![
+            Diagnostic::error("/stats/1/id", "The id 0 is already used by stat number 1.".to_string()),
+            Diagnostic::error("/exit_stage", "There is no stage 9.".to_string()),
+        ]);
+        let pointers: Vec<&str> = found.iter().map(|diagnostic| &diagnostic.pointer[..]).collect();
+        assert_eq!(pointers, vec!["/stats/1/id", "/exit_stage"]);
+    }
+}
@@ -1,20 +1,55 @@
+use schemars::JsonSchema;
+
 use crate::game_state::GameState;
+use crate::ids::Id;
+use crate::locale::{self, Localized};
 
 pub type StatValue = i32;
 
 pub trait Conditional { fn get_condition(&self) -> &Condition; }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct Statistic {
-    pub id: usize,
-    pub name: String,
+    pub id: Id,
+    pub name: Localized<String>,
     #[serde(rename = "default_value")]
     pub value: StatValue,
 }
 
+/// About the game, for its title screen and for library browsers.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct Metadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// The version of the game itself, like "1.2".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<Localized<String>>,
+    /// The language the game is written in, like "en".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// What players may want to know about before playing, like "violence".
+    #[serde(default, skip_serializing_if = "locale::is_empty")]
+    pub content_warnings: Localized<Vec<String>>,
+    /// Shown after the title screen, before the first stage.
+    #[serde(default, skip_serializing_if = "locale::is_empty")]
+    pub intro: Localized<Vec<String>>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.author.is_none() && self.version.is_none() && self.description.is_none() &&
+            self.language.is_none() && self.license.is_none() &&
+            locale::is_empty(&self.content_warnings) && locale::is_empty(&self.intro)
+    }
+}
+
 pub type ItemSlot = String;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum ItemEffect {
     NoEffect,
@@ -28,55 +63,98 @@ pub enum ItemEffect {
     },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct Item {
-    pub id: usize,
-    pub name: String,
+    pub id: Id,
+    pub name: Localized<String>,
     pub effect: ItemEffect,
+    /// Whether the player holds the item, see `GameState::inventory`.
     #[serde(skip)]
     pub in_use: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum Condition {
     Always,
-    IfStatHigher { stat_id: usize, higher_than: StatValue },
-    IfStatLower { stat_id: usize, lower_than: StatValue },
-    IfStatExact { stat_id: usize, value: StatValue },
+    IfStatHigher {
+        stat_id: Id,
+        higher_than: StatValue,
+    },
+    IfStatLower {
+        stat_id: Id,
+        lower_than: StatValue,
+    },
+    IfStatExact {
+        stat_id: Id,
+        value: StatValue,
+    },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum Effect {
     NoEffect,
-    SetStatHigher { stat_id: usize, to_add: StatValue },
-    SetStatLower { stat_id: usize, to_subtract: StatValue },
-    SetStatExact { stat_id: usize, new_value: StatValue },
-    UseItem { item_id: usize },
+    SetStatHigher {
+        stat_id: Id,
+        to_add: StatValue,
+    },
+    SetStatLower {
+        stat_id: Id,
+        to_subtract: StatValue,
+    },
+    SetStatExact {
+        stat_id: Id,
+        new_value: StatValue,
+    },
+    UseItem {
+        item_id: Id,
+    },
+    UnlockAchievement {
+        achievement_id: Id,
+    },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct StageOption {
-    pub target_stage: usize,
-    pub text: Vec<String>,
+    pub target_stage: Id,
+    pub text: Localized<Vec<String>>,
+    /// The option is only shown while this is true.
     #[serde(default = "Condition::always")]
     pub condition: Condition,
     #[serde(default = "Effect::no_effect")]
     pub effect: Effect,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct Stage {
-    pub index: usize,
-    pub name: String,
-    pub text: Vec<String>,
+    /// The id options use in `target_stage` to lead here.
+    pub index: Id,
+    pub name: Localized<String>,
+    pub text: Localized<Vec<String>>,
     pub options: Vec<StageOption>,
     #[serde(skip)]
     pub current_option: usize,
 }
 
-/// FORMAT END
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AchievementTrigger {
+    OnEffect,
+    Live { condition: Condition },
+    OnFinish { condition: Condition },
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct Achievement {
+    pub id: Id,
+    pub name: Localized<String>,
+    #[serde(default)]
+    pub description: Localized<String>,
+    /// When the achievement unlocks, `on_effect` means only through an `unlock_achievement` effect.
+    #[serde(default = "AchievementTrigger::on_effect")]
+    pub trigger: AchievementTrigger,
+}
 
 pub enum Direction {
     Up,
@@ -87,9 +165,9 @@ impl Stage {
     #[allow(unused)]
     pub fn new() -> Self {
         Stage {
-            index: 0,
-            name: String::new(),
-            text: Vec::new(),
+            index: Id::default(),
+            name: Localized::default(),
+            text: Localized::default(),
             options: Vec::new(),
             current_option: 0,
         }
@@ -156,7 +234,7 @@ impl Stage {
 impl Clone for Stage {
     fn clone(&self) -> Self {
         Stage {
-            index: self.index,
+            index: self.index.clone(),
             name: self.name.clone(),
             text: self.text.clone(),
             options: self.options.clone(),
@@ -171,14 +249,41 @@ impl Conditional for StageOption {
     }
 }
 
-impl Condition { fn always() -> Condition { Condition::Always } }
+impl Condition {
+    fn always() -> Condition { Condition::Always }
+    pub fn map_state_id(&self, mapping: &dyn Fn(&Id) -> Result<Id, String>) -> Result<Self, String> {
+        let mut copy = self.clone();
+        match copy {
+            Condition::Always => {}
+            Condition::IfStatExact { ref mut stat_id, value: _ } |
+            Condition::IfStatHigher { ref mut stat_id, higher_than: _ } |
+            Condition::IfStatLower { ref mut stat_id, lower_than: _ } =>
+                *stat_id = mapping(stat_id)?,
+        };
+        Ok(copy)
+    }
+}
+
+impl AchievementTrigger {
+    fn on_effect() -> AchievementTrigger { AchievementTrigger::OnEffect }
+    pub fn map_state_id(&self, mapping: &dyn Fn(&Id) -> Result<Id, String>) -> Result<Self, String> {
+        match self {
+            AchievementTrigger::OnEffect => Ok(AchievementTrigger::OnEffect),
+            AchievementTrigger::Live { condition } =>
+                Ok(AchievementTrigger::Live { condition: condition.map_state_id(mapping)? }),
+            AchievementTrigger::OnFinish { condition } =>
+                Ok(AchievementTrigger::OnFinish { condition: condition.map_state_id(mapping)? }),
+        }
+    }
+}
 
 impl Effect {
     fn no_effect() -> Effect { Effect::NoEffect }
-    pub fn change_stat_id(&self, new_id: usize) -> Option<Self> {
+    pub fn change_stat_id(&self, new_id: Id) -> Option<Self> {
         let mut x = self.clone();
         match x {
-            Effect::NoEffect | Effect::UseItem { item_id: _ } => None,
+            Effect::NoEffect | Effect::UseItem { item_id: _ } |
+            Effect::UnlockAchievement { achievement_id: _ } => None,
             Effect::SetStatLower { ref mut stat_id, to_subtract: _ } |
             Effect::SetStatHigher { ref mut stat_id, to_add: _ } |
             Effect::SetStatExact { ref mut stat_id, new_value: _ } => {
@@ -187,13 +292,14 @@ impl Effect {
             }
         }
     }
-    pub fn map_state_id(&self, mapping: &Fn(usize) -> Result<usize, String>) -> Result<Self, String> {
+    pub fn map_state_id(&self, mapping: &dyn Fn(&Id) -> Result<Id, String>) -> Result<Self, String> {
         match self {
-            Effect::NoEffect | Effect::UseItem { item_id: _ } => Ok(self.clone()),
+            Effect::NoEffect | Effect::UseItem { item_id: _ } |
+            Effect::UnlockAchievement { achievement_id: _ } => Ok(self.clone()),
             Effect::SetStatLower { stat_id, to_subtract: _ } |
             Effect::SetStatHigher { stat_id, to_add: _ } |
             Effect::SetStatExact { stat_id, new_value: _ } => {
-                let new_id = mapping(*stat_id)?;
+                let new_id = mapping(stat_id)?;
                 self.change_stat_id(new_id).ok_or(format!("Invalid stat id {} in effect!", stat_id))
             }
         }
@@ -201,7 +307,7 @@ impl Effect {
 }
 
 impl ItemEffect {
-    pub fn map_state_id(&self, mapping: &Fn(usize) -> Result<usize, String>) -> Result<Self, String> {
+    pub fn map_state_id(&self, mapping: &dyn Fn(&Id) -> Result<Id, String>) -> Result<Self, String> {
         let mut copy = self.clone();
         match copy {
             ItemEffect::NoEffect => Ok(copy),
@@ -219,38 +325,28 @@ impl ItemEffect {
 }
 
 
-impl Copy for Condition {}
-impl Clone for Condition {
-    fn clone(&self) -> Self {
-        *self
-    }
-}
-
-
 impl Clone for ItemEffect {
     fn clone(&self) -> Self {
         match self {
             ItemEffect::Equippable { slot, when_equipped, when_unequipped } =>
-                ItemEffect::Equippable { slot: slot.clone(), when_equipped: *when_equipped, when_unequipped: *when_unequipped },
+                ItemEffect::Equippable {
+                    slot: slot.clone(),
+                    when_equipped: when_equipped.clone(),
+                    when_unequipped: when_unequipped.clone(),
+                },
             ItemEffect::Consumable { on_consume } =>
-                ItemEffect::Consumable { on_consume: *on_consume },
+                ItemEffect::Consumable { on_consume: on_consume.clone() },
             ItemEffect::NoEffect => ItemEffect::NoEffect
         }
     }
 }
-impl Copy for Effect {}
-impl Clone for Effect {
-    fn clone(&self) -> Self {
-        *self
-    }
-}
 impl Clone for StageOption {
     fn clone(&self) -> Self {
         StageOption {
-            target_stage: self.target_stage,
+            target_stage: self.target_stage.clone(),
             text: self.text.clone(),
-            condition: self.condition,
-            effect: self.effect,
+            condition: self.condition.clone(),
+            effect: self.effect.clone(),
         }
     }
 }
\ No newline at end of file
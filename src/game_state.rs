@@ -1,25 +1,68 @@
 use std::collections::HashMap;
 use std::fmt;
-use std::iter::FromIterator;
 use std::iter::Iterator;
 
+use schemars::JsonSchema;
+
 use crate::console::Action;
+use crate::diagnostics::{self, Diagnostic};
 use crate::game_components::*;
+use crate::ids::{Id, Symbols};
+use crate::locale::{self, Translatable};
+use crate::markup::{self, Span, Style};
+use crate::migration::FORMAT_VERSION;
+use crate::save::Snapshot;
+use crate::template::{self, Template};
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A game, as written in a game file.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct GameState {
+    /// The version of the game file format the file was written for, see `advgame migrate`.
+    /// Files without it are from before versions.
+    #[serde(default)]
+    pub format_version: u32,
     pub name: String,
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub metadata: Metadata,
+    /// Files whose stats, stages, item slots, items and achievements are merged into the game,
+    /// relative to this file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
     pub stats: Vec<Statistic>,
     pub stages: Vec<Stage>,
     pub item_slots: Vec<ItemSlot>,
     pub items: Vec<Item>,
-    #[serde(rename = "entry_stage")]
+    /// The stage the game starts in.
+    pub entry_stage: Id,
+    /// The stage that ends the game, the only one without options.
+    pub exit_stage: Id,
+    #[serde(default)]
+    pub achievements: Vec<Achievement>,
+    /// Whether the player may go back to previous choices.
+    #[serde(default = "GameState::allow_undo_default")]
+    pub allow_undo: bool,
+    /// The position of the stage the player is in.
+    #[serde(skip)]
     pub current_stage: usize,
-    pub exit_stage: usize,
     #[serde(skip)]
     finished: bool,
     #[serde(skip)]
     item_slot_filling: HashMap<String, usize>,
+    #[serde(skip)]
+    achievements_unlocked: Vec<bool>,
+    #[serde(skip)]
+    new_achievements: Vec<usize>,
+    #[serde(skip)]
+    pub source_hash: u64,
+    /// Seconds spent playing, including the time from loaded saves.
+    #[serde(skip)]
+    pub playtime: u64,
+    /// Every language some text is translated to, the default one first.
+    #[serde(skip)]
+    languages: Vec<String>,
+    /// Every text that can have placeholders, compiled, see `template`.
+    #[serde(skip)]
+    templates: HashMap<String, Template>,
 }
 
 impl fmt::Display for GameState {
@@ -31,8 +74,90 @@ impl fmt::Display for GameState {
 type ParseResult = Result<GameState, String>;
 
 impl GameState {
+    fn allow_undo_default() -> bool { true }
+
+    /// Creates an empty game, to be filled in by hand before `post_process`.
+    pub fn new(name: String) -> GameState {
+        GameState {
+            format_version: FORMAT_VERSION,
+            name,
+            metadata: Metadata::default(),
+            include: Vec::new(),
+            stats: Vec::new(),
+            stages: Vec::new(),
+            item_slots: Vec::new(),
+            items: Vec::new(),
+            entry_stage: Id::default(),
+            exit_stage: Id::default(),
+            achievements: Vec::new(),
+            allow_undo: GameState::allow_undo_default(),
+            current_stage: 0,
+            finished: false,
+            item_slot_filling: HashMap::new(),
+            achievements_unlocked: Vec::new(),
+            new_achievements: Vec::new(),
+            source_hash: 0,
+            playtime: 0,
+            languages: Vec::new(),
+            templates: HashMap::new(),
+        }
+    }
+
     pub fn get_name(&self) -> &str { &self.name }
 
+    /// Every text players see that can be translated.
+    fn texts_mut(&mut self) -> Vec<&mut dyn Translatable> {
+        let mut texts: Vec<&mut dyn Translatable> = Vec::new();
+        if let Some(description) = &mut self.metadata.description {
+            texts.push(description);
+        }
+        texts.push(&mut self.metadata.content_warnings);
+        texts.push(&mut self.metadata.intro);
+        for stat in &mut self.stats {
+            texts.push(&mut stat.name);
+        }
+        for stage in &mut self.stages {
+            texts.push(&mut stage.name);
+            texts.push(&mut stage.text);
+            for option in &mut stage.options {
+                texts.push(&mut option.text);
+            }
+        }
+        for item in &mut self.items {
+            texts.push(&mut item.name);
+        }
+        for achievement in &mut self.achievements {
+            texts.push(&mut achievement.name);
+            texts.push(&mut achievement.description);
+        }
+        texts
+    }
+
+    /// The languages the game can be played in, starting with `metadata.language` if it's given.
+    /// Empty for games that aren't translated.
+    pub fn languages(&self) -> &[String] {
+        &self.languages
+    }
+
+    fn find_languages(&mut self) {
+        let mut languages: Vec<String> = self.metadata.language.iter().cloned().collect();
+        for text in self.texts_mut() {
+            for locale in text.locales() {
+                if !languages.iter().any(|known| locale::matches(known, locale)) {
+                    languages.push(locale.to_string());
+                }
+            }
+        }
+        self.languages = if languages.len() > 1 { languages } else { Vec::new() };
+    }
+
+    /// Shows all texts in the language from now on. Texts that weren't translated to it keep their default.
+    pub fn set_language(&mut self, locale: &str) {
+        for text in self.texts_mut() {
+            text.select(locale);
+        }
+    }
+
     pub fn check_dead_ends(self) -> ParseResult {
         let it =
             self.stages.iter().filter(|stage| {
@@ -52,128 +177,124 @@ impl GameState {
         }
     }
 
-    pub fn map_stat_ids(mut self) -> ParseResult {
+    pub fn map_stat_ids(mut self, symbols: &Symbols) -> ParseResult {
         dprintln!("map_stat_ids():    {:?}", self);
-        let mapper: HashMap<usize, usize> = HashMap::from_iter(
-            self.stats.iter().enumerate().map(|(i, stat)| {
-                dprintln!("Stat {} becomes stat {}!", stat.id, i);
-                (stat.id, i)
-            })
-        );
-        self.stages.iter_mut().fold(Ok(0), |res, stage| {
-            let stage_index = stage.index;
-            dprintln!("Stage {}:", stage_index);
-            dprintln!("Before:    {:?}", stage);
-            let stage_name = stage.name.clone();
-            let mapping = |x| mapper.get(&x).and_then(|x| Some(*x)).ok_or(format!(
-                "Entry \"{}\" in stage {}, \"{}\" points to an inexistent stat.", x, stage_index, stage_name
-            ));
-            let rv = res.and_then(|state| stage.options.iter_mut().fold(Ok(state), |res, option| {
-                match option.condition {
-                    Condition::Always => {}
-                    Condition::IfStatExact { ref mut stat_id, value: _ } |
-                    Condition::IfStatHigher { ref mut stat_id, higher_than: _ } |
-                    Condition::IfStatLower { ref mut stat_id, lower_than: _ } =>
-                        *stat_id = mapping(*stat_id)?,
-                };
+        let mapping = |id: &Id| symbols.stats.resolve(id).map(Id::from);
+        for stage in self.stages.iter_mut() {
+            for option in stage.options.iter_mut() {
+                option.condition = option.condition.map_state_id(&mapping)?;
                 option.effect = option.effect.map_state_id(&mapping)?;
-                res
-            }));
-            dprintln!("After:     {:?}", stage);
-            rv
-        }).and(self.items.iter_mut().fold(Ok(0), |res, item| {
-            item.effect = item.effect.map_state_id(
-                &|x| mapper.get(&x).and_then(|x| Some(*x)).ok_or(format!("Invalid state id in item {}.", item.name))
-            )?;
-            res
-        })).and(Ok(self))
-    }
-
-    pub fn map_stage_ids(mut self) -> ParseResult {
-        dprintln!("map_stage_ids():    {:?}", self);
-        let mapper: HashMap<usize, usize> = HashMap::from_iter(
-            self.stages.iter().enumerate().map(|(i, stage)| {
-                dprintln!("Stage {} becomes stage {}!", stage.index, i);
-                (stage.index, i)
-            })
-        );
-
-        self.stages.iter_mut().fold(Ok(0), |res, stage| {
-            let stage_index = stage.index;
-            let stage_name = stage.name.clone();
-
-            let mapping = |x: usize| {
-                mapper.get(&x).ok_or(format!(
-                    "Entry \"{}\" in stage {}, \"{}\" points to an inexistent stage.",
-                    x, stage_index, stage_name
-                )).and_then(|x| Ok(*x))
-            };
+            }
+        }
+        for item in self.items.iter_mut() {
+            item.effect = item.effect.map_state_id(&mapping)?;
+        }
+        for achievement in self.achievements.iter_mut() {
+            achievement.trigger = achievement.trigger.map_state_id(&mapping)?;
+        }
+        for (i, stat) in self.stats.iter_mut().enumerate() {
+            dprintln!("Stat {} becomes stat {}!", stat.id, i);
+            stat.id = Id::from(i);
+        }
+        Ok(self)
+    }
 
-            stage.options.iter_mut().fold(res, |res, option| {
+    pub fn map_stage_ids(mut self, symbols: &Symbols) -> ParseResult {
+        dprintln!("map_stage_ids():    {:?}", self);
+        let mapping = |id: &Id| symbols.stages.resolve(id).map(Id::from);
+        for (i, stage) in self.stages.iter_mut().enumerate() {
+            dprintln!("Stage {} becomes stage {}!", stage.index, i);
+            for option in stage.options.iter_mut() {
                 if option.text.is_empty() {
-                    return Err(format!("No text provided for option in stage {}", stage_index));
+                    return Err(format!("No text provided for option in stage {}", stage.index));
                 }
-                option.target_stage = mapping(option.target_stage)?;
-                res
-            }).and(mapper.get(&stage_index).ok_or(
-                "Post processing of data failed. It's a bug on our side. Sorry!".to_string()
-            )).and_then(|mapped_index| {
-                stage.index = *mapped_index;
-                Ok(1)
-            })
-        }).and(
-            mapper.get(&self.current_stage).ok_or("Entry stage is invalid!".to_string())
-        ).and_then(|first_stage| {
-            self.current_stage = *first_stage;
-            mapper.get(&self.exit_stage).ok_or("Exit stage is invalid!".to_string())
-        }).and_then(|stage| {
-            self.exit_stage = *stage;
-            Ok(self)
-        })
+                option.target_stage = mapping(&option.target_stage)?;
+            }
+            stage.index = Id::from(i);
+        }
+        self.entry_stage = mapping(&self.entry_stage)?;
+        self.current_stage = self.entry_stage.position();
+        self.exit_stage = mapping(&self.exit_stage)?;
+        Ok(self)
     }
 
-    pub fn map_item_ids(mut self) -> ParseResult {
+    pub fn map_item_ids(mut self, symbols: &Symbols) -> ParseResult {
         dprintln!("map_item_ids():    {:?}", self);
-        let item_id_mapper: HashMap<usize, usize> = HashMap::from_iter(
-            self.items.iter().enumerate().map(|(i, item)| {
-                dprintln!("Item {} becomes item {}!", item.id, i);
-                (item.id, i)
-            })
-        );
         let effect_mapper = |effect: &mut Effect| match effect {
-            Effect::NoEffect | Effect::SetStatHigher { .. } |
-            Effect::SetStatLower { .. } | Effect::SetStatExact { .. } => Ok(1),
-            Effect::UseItem { ref mut item_id } =>
-                item_id_mapper.get(item_id)
-                    .ok_or(format!("Invalid item {} in a use_item effect.", item_id))
-                    .and_then(|mapped_item_id| {
-                        *item_id = *mapped_item_id;
-                        Ok(1)
-                    }),
+            Effect::UseItem { item_id } => symbols.items.resolve(item_id).map(|position| *item_id = Id::from(position)),
+            _ => Ok(()),
         };
-        self.items.iter_mut().fold(Ok(1), |res, item|
-            res.and(item_id_mapper.get(&item.id)
-                .ok_or(format!("Invalid item {}", item.id)))
-                .and_then(|item_id| {
-                    item.id = *item_id;
-                    match item.effect {
-                        ItemEffect::NoEffect => Ok(1),
-                        ItemEffect::Consumable { ref mut on_consume } =>
-                            effect_mapper(on_consume),
-                        ItemEffect::Equippable { slot: _, ref mut when_equipped, ref mut when_unequipped } =>
-                            effect_mapper(when_equipped).and(effect_mapper(when_unequipped)),
-                    }
-                }),
-        ).and(Ok(self))
+        for stage in self.stages.iter_mut() {
+            for option in stage.options.iter_mut() {
+                effect_mapper(&mut option.effect)?;
+            }
+        }
+        for (i, item) in self.items.iter_mut().enumerate() {
+            dprintln!("Item {} becomes item {}!", item.id, i);
+            item.id = Id::from(i);
+            match item.effect {
+                ItemEffect::NoEffect => {}
+                ItemEffect::Consumable { ref mut on_consume } => effect_mapper(on_consume)?,
+                ItemEffect::Equippable { slot: _, ref mut when_equipped, ref mut when_unequipped } => {
+                    effect_mapper(when_equipped)?;
+                    effect_mapper(when_unequipped)?;
+                }
+            }
+        }
+        Ok(self)
     }
 
-    pub fn post_process(mut self) -> ParseResult {
-        // Check if all stages except the last one have at least one option.
-        self = self.check_dead_ends()?;
+    pub fn map_achievement_ids(mut self, symbols: &Symbols) -> ParseResult {
+        dprintln!("map_achievement_ids():    {:?}", self);
+        let effect_mapper = |effect: &mut Effect| match effect {
+            Effect::UnlockAchievement { achievement_id } =>
+                symbols.achievements.resolve(achievement_id).map(|position| *achievement_id = Id::from(position)),
+            _ => Ok(()),
+        };
+        for stage in self.stages.iter_mut() {
+            for option in stage.options.iter_mut() {
+                effect_mapper(&mut option.effect)?;
+            }
+        }
+        for item in self.items.iter_mut() {
+            match item.effect {
+                ItemEffect::NoEffect => {}
+                ItemEffect::Consumable { ref mut on_consume } => effect_mapper(on_consume)?,
+                ItemEffect::Equippable { slot: _, ref mut when_equipped, ref mut when_unequipped } => {
+                    effect_mapper(when_equipped)?;
+                    effect_mapper(when_unequipped)?;
+                }
+            }
+        }
+        for (i, achievement) in self.achievements.iter_mut().enumerate() {
+            achievement.id = Id::from(i);
+        }
+        self.achievements_unlocked = vec![false; self.achievements.len()];
+        Ok(self)
+    }
+
+    /// Validates the game and maps all ids to indices, looking them up in the symbols of this game.
+    /// Fails with every problem found, warnings included, if there is at least one error.
+    pub fn post_process(mut self, symbols: &Symbols) -> Result<GameState, Vec<Diagnostic>> {
+        let diagnostics = diagnostics::validate(&self, symbols);
+        if diagnostics::has_errors(&diagnostics) {
+            return Err(diagnostics);
+        }
+        // Validation catches everything these check, so failing here is a bug.
+        let bug = |message: String| vec![Diagnostic::error("", format!(
+            "{} Post processing of data failed. It's a bug on our side. Sorry!", message
+        ))];
 
         // Map all IDs to array indices.
-        self = self.map_stat_ids()?.map_stage_ids()?.map_item_ids()?;
+        self = self.map_stat_ids(symbols).and_then(|state| state.map_stage_ids(symbols))
+            .and_then(|state| state.map_item_ids(symbols))
+            .and_then(|state| state.map_achievement_ids(symbols)).map_err(bug)?;
 
+        // Check if all stages except the last one have at least one option.
+        self = self.check_dead_ends().map_err(bug)?;
+
+        self.find_languages();
+        self.compile_templates();
         dprintln!("After map:    {:?}", self);
         // Make sure we start in the correct stage
         self.enter_current_stage();
@@ -218,6 +339,16 @@ impl GameState {
         self.replace_current_stage(f(self.get_current_stage().clone()));
     }
 
+    /// Returns the option that `action` would choose in the current stage, if any.
+    pub fn chosen_option(&self, action: &Action) -> Option<&StageOption> {
+        let stage = self.get_current_stage();
+        match action {
+            Action::Confirm => stage.get_current_option(),
+            Action::Number(num) if stage.has_option(*num) => self.visible_options(stage).nth(*num - 1),
+            _ => None,
+        }
+    }
+
     pub fn handle_action(mut self, action: &Action) -> Self {
         let mut stage_change: Option<StageOption> = None;
         let mut finish = self.finished;
@@ -248,12 +379,8 @@ impl GameState {
             }
             Action::Number(num) => {
                 if stage.has_option(*num) {
-                    let stage = self.get_current_stage().clone();
-                    let it = self.visible_options(&stage).nth(*num - 1);
-                    it.and_then(|num| Some(num.target_stage)).and_then(|ind| {
-                        Some(self.change_to_stage_index(ind))
-                    });
-
+                    // Chosen like with Enter, so the option's effect applies too.
+                    stage_change = self.visible_options(&stage).nth(*num - 1).cloned();
                     dprintln!("Stage may be changed due to {} being pressed.", *num)
                 }
             }
@@ -262,12 +389,109 @@ impl GameState {
         if stage_change.is_some() {
             let op = stage_change.unwrap();
             self.apply_effect(&op.effect);
-            self.change_to_stage_index(op.target_stage);
+            self.change_to_stage_index(op.target_stage.position());
         }
         self.finished = finish;
+        self.check_achievements();
         self
     }
 
+    /// Marks an achievement as already unlocked, e.g. in a previous playthrough,
+    /// so that it is not reported again.
+    pub fn mark_achievement_unlocked(&mut self, achievement_id: usize) {
+        if let Some(unlocked) = self.achievements_unlocked.get_mut(achievement_id) {
+            *unlocked = true;
+        }
+    }
+
+    fn unlock_achievement(&mut self, achievement_id: usize) {
+        if !self.achievements_unlocked[achievement_id] {
+            dprintln!("Unlocked achievement {}!", achievement_id);
+            self.achievements_unlocked[achievement_id] = true;
+            self.new_achievements.push(achievement_id);
+        }
+    }
+
+    /// Unlocks all achievements whose conditions are met by the current state.
+    pub fn check_achievements(&mut self) {
+        let to_unlock: Vec<usize> = self.achievements.iter()
+            .filter(|achievement| match &achievement.trigger {
+                AchievementTrigger::OnEffect => false,
+                AchievementTrigger::Live { condition } => self.is_condition_met(condition),
+                AchievementTrigger::OnFinish { condition } =>
+                    self.finished && self.is_condition_met(condition),
+            })
+            .map(|achievement| achievement.id.position())
+            .collect();
+        to_unlock.into_iter().for_each(|id| self.unlock_achievement(id));
+    }
+
+    /// Returns the achievements unlocked since the last call.
+    pub fn take_new_achievements(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.new_achievements)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            stats: self.stats.iter().map(|stat| stat.value).collect(),
+            current_stage: self.current_stage,
+            current_option: self.get_current_stage().current_option,
+            item_slot_filling: self.item_slot_filling.clone(),
+            held_items: self.inventory().map(|item| item.id.position()).collect(),
+            finished: self.finished,
+        }
+    }
+
+    /// Replaces the runtime state with the snapshot after checking that it fits this game.
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), String> {
+        if snapshot.stats.len() != self.stats.len() {
+            return Err(format!("Saved state has {} stats, but the game has {}.",
+                               snapshot.stats.len(), self.stats.len()));
+        }
+        if snapshot.current_stage >= self.stages.len() {
+            return Err(format!("Saved state points to an inexistent stage {}.", snapshot.current_stage));
+        }
+        let stage = &self.stages[snapshot.current_stage];
+        if snapshot.current_option != 0 && !stage.has_option(snapshot.current_option) {
+            return Err(format!("Saved state points to an inexistent option {} in stage \"{}\".",
+                               snapshot.current_option, stage.name));
+        }
+        for (slot, item_id) in snapshot.item_slot_filling.iter() {
+            if !self.item_slots.contains(slot) {
+                return Err(format!("Saved state uses an inexistent item slot \"{}\".", slot));
+            }
+            if *item_id >= self.items.len() {
+                return Err(format!("Saved state puts an inexistent item {} in slot \"{}\".", item_id, slot));
+            }
+        }
+        if let Some(item_id) = snapshot.held_items.iter().find(|&&item_id| item_id >= self.items.len()) {
+            return Err(format!("Saved state holds an inexistent item {}.", item_id));
+        }
+
+        for (stat, value) in self.stats.iter_mut().zip(snapshot.stats) {
+            stat.value = value;
+        }
+        self.current_stage = snapshot.current_stage;
+        self.get_current_stage_mut().current_option = snapshot.current_option;
+        for (i, item) in self.items.iter_mut().enumerate() {
+            item.in_use = snapshot.held_items.contains(&i) || snapshot.item_slot_filling.values().any(|&id| id == i);
+        }
+        self.item_slot_filling = snapshot.item_slot_filling;
+        self.finished = snapshot.finished;
+        Ok(())
+    }
+
+    /// The item in the slot, if any.
+    pub fn equipped(&self, slot: &str) -> Option<&Item> {
+        self.item_slot_filling.get(slot).and_then(|&id| self.items.get(id))
+    }
+
+    /// The items the player holds, in the order of the game file: those equipped,
+    /// and those without an effect that were used. Consumables are used up.
+    pub fn inventory(&self) -> impl Iterator<Item=&Item> {
+        self.items.iter().filter(|item| item.in_use)
+    }
+
     pub fn is_finished(&self) -> bool {
         self.finished
     }
@@ -279,14 +503,18 @@ impl GameState {
     }
 
     pub fn is_filled<T>(&self, conditional: &T) -> bool where T: Conditional {
-        match conditional.get_condition() {
+        self.is_condition_met(conditional.get_condition())
+    }
+
+    pub fn is_condition_met(&self, condition: &Condition) -> bool {
+        match condition {
             Condition::Always => true,
             Condition::IfStatHigher { stat_id, higher_than } =>
-                self.stats[*stat_id].value > *higher_than,
+                self.stats[stat_id.position()].value > *higher_than,
             Condition::IfStatLower { stat_id, lower_than } =>
-                self.stats[*stat_id].value < *lower_than,
+                self.stats[stat_id.position()].value < *lower_than,
             Condition::IfStatExact { stat_id, value } =>
-                self.stats[*stat_id].value == *value,
+                self.stats[stat_id.position()].value == *value,
         }
     }
 
@@ -300,16 +528,21 @@ impl GameState {
     }
 
     fn apply_effect(&mut self, effect: &Effect) {
-        match *effect {
+        match effect {
             Effect::NoEffect => {}
             Effect::SetStatExact { stat_id, new_value } =>
-                self.stats[stat_id].value = new_value,
+                self.stats[stat_id.position()].value = *new_value,
             Effect::SetStatHigher { stat_id, to_add } =>
-                self.stats[stat_id].value += to_add,
+                self.stats[stat_id.position()].value += to_add,
             Effect::SetStatLower { stat_id, to_subtract } =>
-                self.stats[stat_id].value -= to_subtract,
+                self.stats[stat_id.position()].value -= to_subtract,
             Effect::UseItem { item_id } => {
+                let item_id = item_id.position();
                 let item = &self.items[item_id];
+                let unequipped = match &item.effect {
+                    ItemEffect::Equippable { slot, .. } => self.item_slot_filling.get(slot).cloned(),
+                    _ => None,
+                };
                 //let eff: Vec<Effect> =
                 match &item.effect {
                     ItemEffect::NoEffect => { [].to_vec() }
@@ -317,38 +550,55 @@ impl GameState {
                         [on_consume.clone()].to_vec()
                     }
                     ItemEffect::Equippable { slot, when_equipped, when_unequipped: _ } => {
-                        let prev = self.item_slot_filling.insert(slot.clone(), item.id).and_then(|i| match self.items[i].effect {
-                            ItemEffect::Equippable { when_unequipped, .. } => Some(when_unequipped),
+                        let prev = self.item_slot_filling.insert(slot.clone(), item_id).and_then(|i| match &self.items[i].effect {
+                            ItemEffect::Equippable { when_unequipped, .. } => Some(when_unequipped.clone()),
                             _ => None
                         }).unwrap_or(Effect::NoEffect);
-                        [*when_equipped, prev.clone()].to_vec()
+                        [when_equipped.clone(), prev].to_vec()
                     }
                 }.iter().for_each(|eff| self.apply_effect(eff));
+                // Consumables are used up, other items are kept, see `inventory`.
+                if let Some(unequipped) = unequipped {
+                    self.items[unequipped].in_use = false;
+                }
+                self.items[item_id].in_use = !matches!(self.items[item_id].effect, ItemEffect::Consumable { .. });
             }
+            Effect::UnlockAchievement { achievement_id } =>
+                self.unlock_achievement(achievement_id.position()),
         }
     }
 
-    fn parse_format_tiny(&self, text: &str) -> String {
-        let split_text: Vec<&str> = text.split('.').collect();
-        match split_text[0] {
-            "stat" => split_text[1].parse::<usize>()
-                .and_then(|x| { Ok(&self.stats[x]) })
-                .or(self.stats.iter().find(|stat| stat.name == split_text[1]).ok_or(""))
-                .and_then(|stat| Ok(format!("{}", stat.value))),
-            "item" => split_text[1].parse::<usize>()
-                .and_then(|x| { Ok(&self.items[x]) })
-                .or(self.items.iter().find(|item| item.name == split_text[1]).ok_or(""))
-                .and_then(|x| Ok(x.name.clone())),
-            _ => Err("")
-        }.unwrap_or(text.to_string())
+    /// Compiles every text that can have placeholders in every language, so they aren't read again
+    /// whenever they're shown. Validation made sure they all compile.
+    fn compile_templates(&mut self) {
+        let texts = self.metadata.intro.values().flatten()
+            .chain(self.stages.iter().flat_map(|stage| stage.text.values().flatten()
+                .chain(stage.options.iter().flat_map(|option| option.text.values().flatten()))))
+            .chain(self.items.iter().flat_map(|item| item.name.values()));
+        let templates = texts.filter_map(|text| template::compile(text, self).ok().map(|t| (text.clone(), t)))
+            .collect();
+        self.templates = templates;
     }
 
-    pub fn parse_format_text(&self, text: &str) -> String {
-        text.split('$').enumerate().fold("".to_string(), |acc, (i, pat): (usize, &str)|
-            if i == 0 || &pat[0..1] != "{" || !pat.contains('}') { pat.to_string() } else {
-                let ind = pat.find('}').unwrap();
-                acc + &self.parse_format_tiny(&pat[1..ind]) + &pat[ind + 1..]
+    /// Replaces the placeholders in a text, see `template`, and reads its markup.
+    /// Texts that aren't part of the game are compiled on the spot, and shown as written if they don't compile.
+    pub fn format_text(&self, text: &str) -> Vec<Span> {
+        let compiled;
+        let template = match self.templates.get(text) {
+            Some(template) => template,
+            None => match template::compile(text, self) {
+                Ok(template) => {
+                    compiled = template;
+                    &compiled
+                }
+                Err(_) => return vec![Span { text: text.to_string(), style: Style::default() }],
             },
-        )
+        };
+        template.render(self)
+    }
+
+    /// Like `format_text`, without the styles, for places that can't show them.
+    pub fn parse_format_text(&self, text: &str) -> String {
+        markup::plain(&self.format_text(text))
     }
 }
@@ -0,0 +1,135 @@
+//! Draws the stages of a game and the options between them, for `advgame graph`,
+//! as Graphviz DOT or as a Mermaid flowchart.
+
+use crate::game_components::*;
+use crate::game_state::GameState;
+use crate::ids::Id;
+use crate::locale::Localized;
+use crate::markup;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+impl GraphFormat {
+    pub const NAMES: &'static str = "dot|mermaid";
+
+    pub fn from_name(name: &str) -> Option<GraphFormat> {
+        match name {
+            "dot" => Some(GraphFormat::Dot),
+            "mermaid" => Some(GraphFormat::Mermaid),
+            _ => None,
+        }
+    }
+}
+
+/// Names are shown without their markup, like everywhere outside the game's screen.
+fn name(name: Option<&Localized<String>>) -> String {
+    name.map_or("?".to_string(), |name| markup::strip(name))
+}
+
+fn stat_name(game: &GameState, stat_id: &Id) -> String {
+    name(game.stats.get(stat_id.position()).map(|stat| &stat.name))
+}
+
+/// Like `if Attack > 100`, or nothing for options that are always shown.
+fn describe_condition(game: &GameState, condition: &Condition) -> Option<String> {
+    match condition {
+        Condition::Always => None,
+        Condition::IfStatHigher { stat_id, higher_than } =>
+            Some(format!("if {} > {}", stat_name(game, stat_id), higher_than)),
+        Condition::IfStatLower { stat_id, lower_than } =>
+            Some(format!("if {} < {}", stat_name(game, stat_id), lower_than)),
+        Condition::IfStatExact { stat_id, value } =>
+            Some(format!("if {} = {}", stat_name(game, stat_id), value)),
+    }
+}
+
+/// Like `Attack += 2`, or nothing for options without an effect.
+fn describe_effect(game: &GameState, effect: &Effect) -> Option<String> {
+    match effect {
+        Effect::NoEffect => None,
+        Effect::SetStatHigher { stat_id, to_add } => Some(format!("{} += {}", stat_name(game, stat_id), to_add)),
+        Effect::SetStatLower { stat_id, to_subtract } =>
+            Some(format!("{} -= {}", stat_name(game, stat_id), to_subtract)),
+        Effect::SetStatExact { stat_id, new_value } => Some(format!("{} = {}", stat_name(game, stat_id), new_value)),
+        Effect::UseItem { item_id } =>
+            Some(format!("use {}", name(game.items.get(item_id.position()).map(|item| &item.name)))),
+        Effect::UnlockAchievement { achievement_id } =>
+            Some(format!("unlock {}", name(game.achievements.get(achievement_id.position()).map(|a| &a.name)))),
+    }
+}
+
+/// The lines an option is labelled with: its first line of text, then what it needs and what it does.
+fn option_label(game: &GameState, option: &StageOption) -> Vec<String> {
+    option.text.first().map(|line| markup::strip(line)).into_iter()
+        .chain(describe_condition(game, &option.condition).map(|condition| format!("[{}]", condition)))
+        .chain(describe_effect(game, &option.effect).map(|effect| format!("{{{}}}", effect)))
+        .collect()
+}
+
+fn dot_string<S: AsRef<str>>(lines: &[S]) -> String {
+    let lines: Vec<String> = lines.iter()
+        .map(|line| line.as_ref().replace('\\', "\\\\").replace('"', "\\\""))
+        .collect();
+    format!("\"{}\"", lines.join("\\n"))
+}
+
+fn render_dot(game: &GameState) -> String {
+    let mut text = format!("digraph {} {{\n", dot_string(&[markup::strip(&game.name)]));
+    text += "    node [shape=box];\n";
+    for (i, stage) in game.stages.iter().enumerate() {
+        let style = if i == game.current_stage {
+            ", style=filled, fillcolor=palegreen, penwidth=2"
+        } else if i == game.exit_stage.position() {
+            ", style=filled, fillcolor=lightcoral, penwidth=2"
+        } else {
+            ""
+        };
+        text += &format!("    s{} [label={}{}];\n", i, dot_string(&[markup::strip(&stage.name)]), style);
+    }
+    for (i, stage) in game.stages.iter().enumerate() {
+        for option in &stage.options {
+            text += &format!("    s{} -> s{} [label={}];\n", i, option.target_stage.position(),
+                             dot_string(&option_label(game, option)));
+        }
+    }
+    text + "}\n"
+}
+
+/// Mermaid has no escapes in quoted labels, only HTML entities.
+/// Quoted, with what Mermaid reads as markup written as entities, so any text can be a label.
+fn mermaid_string<S: AsRef<str>>(lines: &[S]) -> String {
+    let lines: Vec<String> = lines.iter()
+        .map(|line| line.as_ref().replace('#', "#35;").replace('"', "#quot;").replace('|', "#124;")
+            .replace('<', "#lt;").replace('>', "#gt;"))
+        .collect();
+    format!("\"{}\"", lines.join("<br>"))
+}
+
+fn render_mermaid(game: &GameState) -> String {
+    let mut text = "flowchart TD\n".to_string();
+    for (i, stage) in game.stages.iter().enumerate() {
+        text += &format!("    s{}[{}]\n", i, mermaid_string(&[markup::strip(&stage.name)]));
+    }
+    for (i, stage) in game.stages.iter().enumerate() {
+        for option in &stage.options {
+            text += &format!("    s{} -->|{}| s{}\n", i, mermaid_string(&option_label(game, option)),
+                             option.target_stage.position());
+        }
+    }
+    text += "    classDef entry fill:#cfc,stroke-width:3px\n";
+    text += "    classDef exit fill:#fcc,stroke-width:3px\n";
+    text += &format!("    class s{} entry\n", game.current_stage);
+    text + &format!("    class s{} exit\n", game.exit_stage.position())
+}
+
+/// Draws a post processed game, in which stages are referred to by their positions.
+pub fn render_graph(game: &GameState, format: GraphFormat) -> String {
+    match format {
+        GraphFormat::Dot => render_dot(game),
+        GraphFormat::Mermaid => render_mermaid(game),
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+
+use crate::save::Snapshot;
+
+/// A bounded stack of snapshots used for undoing choices.
+/// When it is full, the oldest snapshot is forgotten.
+pub struct History {
+    snapshots: VecDeque<Snapshot>,
+    limit: usize,
+}
+
+impl History {
+    pub fn new(limit: usize) -> History {
+        History {
+            snapshots: VecDeque::with_capacity(limit),
+            limit,
+        }
+    }
+
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.snapshots.len() == self.limit {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    pub fn pop(&mut self) -> Option<Snapshot> {
+        self.snapshots.pop_back()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at_stage(stage: usize) -> Snapshot {
+        Snapshot {
+            stats: Vec::new(),
+            current_stage: stage,
+            current_option: 0,
+            item_slot_filling: Default::default(),
+            held_items: Vec::new(),
+            finished: false,
+        }
+    }
+
+    fn popped(history: &mut History) -> Vec<usize> {
+        std::iter::from_fn(|| history.pop()).map(|snapshot| snapshot.current_stage).collect()
+    }
+
+    #[test]
+    fn undoes_the_latest_choice_first() {
+        let mut history = History::new(5);
+        (0..3).for_each(|stage| history.push(at_stage(stage)));
+        assert_eq!(popped(&mut history), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn forgets_the_oldest_choices_when_full() {
+        let mut history = History::new(2);
+        (0..4).for_each(|stage| history.push(at_stage(stage)));
+        assert_eq!(popped(&mut history), vec![3, 2]);
+    }
+
+    #[test]
+    fn keeps_nothing_without_a_limit() {
+        let mut history = History::new(0);
+        history.push(at_stage(0));
+        assert!(history.pop().is_none());
+    }
+}
@@ -0,0 +1,214 @@
+//! Ids in game files can be numbers, like `"target_stage": 4`, or names, like `"target_stage": "cave"`.
+//! A name is either an id written as text, like `"index": "cave"`, or the name of what it points to,
+//! in any language and with or without its markup, like `"stat_id": "Attack"`, which is how
+//! `${stat.Attack}` finds stats too. `Symbols` resolves both for one loaded game, and
+//! `GameState::post_process` replaces every id with the position it stands for.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::game_state::GameState;
+use crate::locale::Localized;
+use crate::markup;
+
+/// Either a positive number or a name, like 4 or "cave".
+#[derive(JsonSchema, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum Id {
+    Number(usize),
+    Name(String),
+}
+
+impl Id {
+    /// The position of what the id points to, once `GameState::post_process` mapped it.
+    pub fn position(&self) -> usize {
+        match self {
+            Id::Number(position) => *position,
+            Id::Name(name) => panic!("The id \"{}\" was never mapped to a position.", name),
+        }
+    }
+}
+
+impl From<usize> for Id {
+    fn from(number: usize) -> Id {
+        Id::Number(number)
+    }
+}
+
+impl Default for Id {
+    fn default() -> Id {
+        Id::Number(0)
+    }
+}
+
+/// Shows the id the way it was written in the game file, for error messages.
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Id::Number(number) => write!(f, "{}", number),
+            Id::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+struct IdVisitor;
+
+impl<'de> Visitor<'de> for IdVisitor {
+    type Value = Id;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a positive number or a name")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Id, E> {
+        Ok(Id::Number(value as usize))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Id, E> {
+        if value < 0 {
+            return Err(E::invalid_value(de::Unexpected::Signed(value), &self));
+        }
+        self.visit_u64(value as u64)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Id, E> {
+        match value.trim() {
+            "" => Err(E::invalid_value(de::Unexpected::Str(value), &self)),
+            name => Ok(name.parse::<usize>().map_or_else(|_| Id::Name(name.to_string()), Id::Number)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Id {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Id, D::Error> {
+        deserializer.deserialize_any(IdVisitor)
+    }
+}
+
+impl Serialize for Id {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Id::Number(number) => serializer.serialize_u64(*number as u64),
+            Id::Name(name) => serializer.serialize_str(name),
+        }
+    }
+}
+
+/// The ids and names of one kind of thing in a game, like its stats.
+#[derive(Default)]
+pub struct Table {
+    kind: &'static str,
+    /// Positions by id, the first one wins if an id is used twice, see `diagnostics::validate`.
+    ids: HashMap<Id, usize>,
+    /// Positions by name, every name a thing has in any language, with and without markup.
+    names: HashMap<String, Vec<usize>>,
+}
+
+impl Table {
+    fn new<'a, I: Iterator<Item=(&'a Id, &'a Localized<String>)>>(kind: &'static str, entries: I) -> Table {
+        let mut table = Table { kind, ..Table::default() };
+        for (i, (id, name)) in entries.enumerate() {
+            table.ids.entry(id.clone()).or_insert(i);
+            for name in name.values() {
+                for name in [name.clone(), markup::strip(name)] {
+                    let positions = table.names.entry(name).or_default();
+                    if !positions.contains(&i) {
+                        positions.push(i);
+                    }
+                }
+            }
+        }
+        table
+    }
+
+    /// The position of what the id points to. Ids win over names, and a name only counts
+    /// if nothing else is called the same.
+    pub fn resolve(&self, id: &Id) -> Result<usize, String> {
+        if let Some(position) = self.ids.get(id) {
+            return Ok(*position);
+        }
+        match id {
+            Id::Name(name) => match self.names.get(name).map(|positions| &positions[..]) {
+                Some([position]) => Ok(*position),
+                Some(positions) if !positions.is_empty() => Err(format!(
+                    "There are {} {}s called {}, point to one of them by its id.", positions.len(), self.kind, id
+                )),
+                _ => Err(format!("There is no {} {}.", self.kind, id)),
+            },
+            Id::Number(_) => Err(format!("There is no {} {}.", self.kind, id)),
+        }
+    }
+}
+
+/// What the ids in one game stand for, made for every game that's loaded, after its includes are merged.
+pub struct Symbols {
+    pub stats: Table,
+    pub stages: Table,
+    pub items: Table,
+    pub achievements: Table,
+}
+
+impl Symbols {
+    pub fn new(game: &GameState) -> Symbols {
+        Symbols {
+            stats: Table::new("stat", game.stats.iter().map(|stat| (&stat.id, &stat.name))),
+            stages: Table::new("stage", game.stages.iter().map(|stage| (&stage.index, &stage.name))),
+            items: Table::new("item", game.items.iter().map(|item| (&item.id, &item.name))),
+            achievements: Table::new("achievement", game.achievements.iter().map(|a| (&a.id, &a.name))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(json: &str) -> Result<Id, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    fn table(entries: &[(Id, &str)]) -> Table {
+        let entries: Vec<(Id, Localized<String>)> = entries.iter()
+            .map(|(id, name)| (id.clone(), Localized::new(name.to_string())))
+            .collect();
+        Table::new("stat", entries.iter().map(|(id, name)| (id, name)))
+    }
+
+    fn name(name: &str) -> Id {
+        Id::Name(name.to_string())
+    }
+
+    #[test]
+    fn reads_numbers_and_names() {
+        assert_eq!(id("4").unwrap(), Id::Number(4));
+        assert_eq!(id("\" 4 \"").unwrap(), Id::Number(4));
+        assert_eq!(id("\"cave\"").unwrap(), name("cave"));
+        assert!(id("-1").is_err());
+        assert!(id("\" \"").is_err());
+        assert_eq!(serde_json::to_string(&name("cave")).unwrap(), "\"cave\"");
+        assert_eq!(format!("{} {}", Id::Number(4), name("cave")), "4 \"cave\"");
+    }
+
+    #[test]
+    fn resolves_ids_before_names() {
+        let table = table(&[(name("attack"), "Attack"), (Id::Number(7), "attack"), (name("hp"), "*HP*")]);
+        assert_eq!(table.resolve(&name("attack")), Ok(0));
+        assert_eq!(table.resolve(&Id::Number(7)), Ok(1));
+        assert_eq!(table.resolve(&name("Attack")), Ok(0));
+        assert_eq!(table.resolve(&name("HP")), Ok(2));
+        assert_eq!(table.resolve(&name("*HP*")), Ok(2));
+    }
+
+    #[test]
+    fn reports_missing_and_ambiguous_names() {
+        let table = table(&[(Id::Number(0), "Gold"), (Id::Number(1), "Gold")]);
+        assert_eq!(table.resolve(&name("Gold")),
+                   Err("There are 2 stats called \"Gold\", point to one of them by its id.".to_string()));
+        assert_eq!(table.resolve(&name("Mana")), Err("There is no stat \"Mana\".".to_string()));
+        assert_eq!(table.resolve(&Id::Number(2)), Err("There is no stat 2.".to_string()));
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+
+use crate::diagnostics::{self, Diagnostic};
+use crate::game_components::*;
+use crate::game_state::GameState;
+use crate::ids::Id;
+use crate::locale::Localized;
+
+/// A file included by a game, see `GameState::include`.
+/// Everything in it is merged into the including game before post processing,
+/// so ids are shared between all files of a game.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct GamePart {
+    /// See `GameState::format_version`.
+    #[serde(default)]
+    pub format_version: u32,
+    /// More files to include, relative to this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stats: Vec<Statistic>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<Stage>,
+    /// Slots that already exist are not added again.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub item_slots: Vec<ItemSlot>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<Item>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub achievements: Vec<Achievement>,
+}
+
+/// Remembers which file defined every id, so that collisions can name both files.
+pub struct Merger {
+    stats: HashMap<Id, String>,
+    stages: HashMap<Id, String>,
+    items: HashMap<Id, String>,
+    achievements: HashMap<Id, String>,
+    /// Ids an included file defines again, pointing into the merged game.
+    /// Ids used twice in one file are left to `diagnostics::validate`.
+    pub collisions: Vec<Diagnostic>,
+}
+
+/// Remembers the file that defined every id first, and reports ids an earlier file already defined.
+/// `pointer` tells where the id of an entry is in the merged game.
+fn claim<'a, I>(owners: &mut HashMap<Id, String>, collisions: &mut Vec<Diagnostic>, kind: &str,
+                pointer: &dyn Fn(usize) -> String, entries: I, file: &str)
+    where I: Iterator<Item=(&'a Id, &'a Localized<String>)> {
+    for (i, (id, name)) in entries.enumerate() {
+        match owners.get(id) {
+            Some(owner) if owner != file => collisions.push(Diagnostic {
+                subject: diagnostics::subject(kind, name),
+                ..Diagnostic::error(&pointer(i), format!("The id {} is already used in \"{}\".", id, owner))
+            }),
+            Some(_) => {}
+            None => {
+                owners.insert(id.clone(), file.to_string());
+            }
+        }
+    }
+}
+
+impl Merger {
+    /// Starts with the ids of the game itself, read from `file`.
+    pub fn new(state: &GameState, file: &str) -> Merger {
+        let mut merger = Merger {
+            stats: HashMap::new(),
+            stages: HashMap::new(),
+            items: HashMap::new(),
+            achievements: HashMap::new(),
+            collisions: Vec::new(),
+        };
+        merger.claim_all([0; 4], &state.stats, &state.stages, &state.items, &state.achievements, file);
+        merger
+    }
+
+    /// Claims the ids of lists that are appended to the game at the positions in `first`,
+    /// see `diagnostics::list_lengths`.
+    fn claim_all(&mut self, first: [usize; 4], stats: &[Statistic], stages: &[Stage], items: &[Item],
+                 achievements: &[Achievement], file: &str) {
+        claim(&mut self.stats, &mut self.collisions, "stat", &|i| format!("/stats/{}/id", first[0] + i),
+              stats.iter().map(|stat| (&stat.id, &stat.name)), file);
+        claim(&mut self.stages, &mut self.collisions, "stage", &|i| format!("/stages/{}/index", first[1] + i),
+              stages.iter().map(|stage| (&stage.index, &stage.name)), file);
+        claim(&mut self.items, &mut self.collisions, "item", &|i| format!("/items/{}/id", first[2] + i),
+              items.iter().map(|item| (&item.id, &item.name)), file);
+        claim(&mut self.achievements, &mut self.collisions, "achievement",
+              &|i| format!("/achievements/{}/id", first[3] + i), achievements.iter().map(|a| (&a.id, &a.name)), file);
+    }
+
+    /// Adds the contents of an included file to the game.
+    pub fn merge(&mut self, state: &mut GameState, part: GamePart, file: &str) {
+        self.claim_all(diagnostics::list_lengths(state), &part.stats, &part.stages, &part.items,
+                       &part.achievements, file);
+        state.stats.extend(part.stats);
+        state.stages.extend(part.stages);
+        for slot in part.item_slots {
+            if !state.item_slots.contains(&slot) {
+                state.item_slots.push(slot);
+            }
+        }
+        state.items.extend(part.items);
+        state.achievements.extend(part.achievements);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(stats: serde_json::Value) -> Vec<Statistic> {
+        serde_json::from_value(stats).unwrap()
+    }
+
+    fn game() -> GameState {
+        let mut game = GameState::new("Test".to_string());
+        game.stats = stats(serde_json::json!([{"id": 0, "name": "HP", "default_value": 1}]));
+        game
+    }
+
+    fn part(stats: Vec<Statistic>) -> GamePart {
+        GamePart {
+            format_version: 0,
+            include: Vec::new(),
+            stats,
+            stages: Vec::new(),
+            item_slots: vec!["weapon".to_string()],
+            items: Vec::new(),
+            achievements: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reports_ids_earlier_files_defined() {
+        let mut game = game();
+        let mut merger = Merger::new(&game, "main.json");
+        merger.merge(&mut game, part(stats(serde_json::json!([
+            {"id": 1, "name": "Gold", "default_value": 0},
+            {"id": 0, "name": "*Mana*", "default_value": 0},
+        ]))), "part.json");
+        assert_eq!(game.stats.len(), 3);
+        assert_eq!(game.item_slots, vec!["weapon".to_string()]);
+        let collisions: Vec<String> = merger.collisions.iter().map(ToString::to_string).collect();
+        assert_eq!(collisions, vec!["error: /stats/2/id, stat \"Mana\": The id 0 is already used in \"main.json\".".to_string()]);
+    }
+
+    #[test]
+    fn leaves_ids_used_twice_in_one_file_to_validation() {
+        let mut game = game();
+        let mut merger = Merger::new(&game, "main.json");
+        merger.merge(&mut game, part(stats(serde_json::json!([
+            {"id": 5, "name": "Gold", "default_value": 0},
+            {"id": 5, "name": "Mana", "default_value": 0},
+        ]))), "part.json");
+        assert!(merger.collisions.is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+//! Text players see can be written once, or once per language, like `"name": "Sword"` or
+//! `"name": {"en": "Sword", "pl": "Miecz"}`. The first language is the default, shown when the game
+//! is played in a language the text wasn't translated to. See `GameState::set_language`.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Metadata, ObjectValidation, Schema, SchemaObject, SubschemaValidation};
+use schemars::JsonSchema;
+use serde::de::value::SeqAccessDeserializer;
+use serde::de::{self, Deserialize, Deserializer, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+/// Whether a locale like "pl-PL" is meant by another one, like "pl", in either direction.
+pub fn matches(locale: &str, wanted: &str) -> bool {
+    let primary = |locale: &str| locale.split(['-', '_']).next().unwrap_or("").to_lowercase();
+    locale.eq_ignore_ascii_case(wanted) || primary(locale) == primary(wanted)
+}
+
+/// How a language calls itself, with the locale, like "Polski (pl)".
+pub fn language_name(locale: &str) -> String {
+    const NAMES: [(&str, &str); 12] = [
+        ("cs", "Čeština"), ("de", "Deutsch"), ("en", "English"), ("es", "Español"), ("fr", "Français"),
+        ("it", "Italiano"), ("ja", "日本語"), ("pl", "Polski"), ("pt", "Português"), ("ru", "Русский"),
+        ("uk", "Українська"), ("zh", "中文"),
+    ];
+    match NAMES.iter().find(|(known, _)| matches(known, locale)) {
+        Some((_, name)) => format!("{} ({})", name, locale),
+        None => locale.to_string(),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Localized<T> {
+    /// Values by locale, the first one is the default.
+    /// A value written without a locale is the only one, with an empty locale.
+    values: Vec<(String, T)>,
+    /// The value that is shown, see `select`.
+    current: usize,
+}
+
+impl<T> Localized<T> {
+    /// A value that is the same in every language.
+    pub fn new(value: T) -> Localized<T> {
+        Localized { values: vec![(String::new(), value)], current: 0 }
+    }
+
+    /// The value in the default language, which stays the same whatever language is played.
+    pub fn original(&self) -> &T {
+        &self.values[0].1
+    }
+
+    /// The values in all languages.
+    pub fn values(&self) -> impl Iterator<Item=&T> {
+        self.values.iter().map(|(_, value)| value)
+    }
+
+    /// The values with their locales, which are empty for values written without one.
+    pub fn entries(&self) -> impl Iterator<Item=(&str, &T)> {
+        self.values.iter().map(|(locale, value)| (&locale[..], value))
+    }
+}
+
+impl Localized<String> {
+    /// Whether the text is the value in any of the languages, to look things up by name.
+    pub fn has(&self, text: &str) -> bool {
+        self.values().any(|value| value == text)
+    }
+}
+
+impl<T: Default> Default for Localized<T> {
+    fn default() -> Localized<T> {
+        Localized::new(T::default())
+    }
+}
+
+impl<T> Deref for Localized<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.values[self.current].1
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Localized<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", **self)
+    }
+}
+
+/// What `GameState` needs to switch all its texts to another language.
+pub trait Translatable {
+    fn locales(&self) -> Vec<&str>;
+
+    /// Shows the value for the locale from now on, or the default one if there's none.
+    fn select(&mut self, locale: &str);
+}
+
+impl<T> Translatable for Localized<T> {
+    fn locales(&self) -> Vec<&str> {
+        self.values.iter().map(|(locale, _)| &locale[..]).filter(|locale| !locale.is_empty()).collect()
+    }
+
+    fn select(&mut self, locale: &str) {
+        self.current = self.values.iter().position(|(known, _)| known.eq_ignore_ascii_case(locale))
+            .or_else(|| self.values.iter().position(|(known, _)| matches(known, locale)))
+            .unwrap_or(0);
+    }
+}
+
+/// For `skip_serializing_if` on texts with many lines.
+pub fn is_empty<T>(text: &Localized<Vec<T>>) -> bool {
+    text.values().all(Vec::is_empty)
+}
+
+impl<T: Serialize> Serialize for Localized<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.values[..] {
+            [(locale, value)] if locale.is_empty() => value.serialize(serializer),
+            values => {
+                let mut map = serializer.serialize_map(Some(values.len()))?;
+                for (locale, value) in values {
+                    map.serialize_entry(locale, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+struct LocalizedVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for LocalizedVisitor<T> {
+    type Value = Localized<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a value, or a map from locales to values")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Localized<T>, E> {
+        T::deserialize(value.into_deserializer()).map(Localized::new)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Localized<T>, A::Error> {
+        T::deserialize(SeqAccessDeserializer::new(seq)).map(Localized::new)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Localized<T>, A::Error> {
+        let mut values = Vec::new();
+        while let Some((locale, value)) = map.next_entry::<String, T>()? {
+            if locale.trim().is_empty() {
+                return Err(de::Error::custom("locales can't be empty"));
+            }
+            values.push((locale, value));
+        }
+        if values.is_empty() {
+            return Err(de::Error::invalid_length(0, &"at least one locale"));
+        }
+        Ok(Localized { values, current: 0 })
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Localized<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Localized<T>, D::Error> {
+        deserializer.deserialize_any(LocalizedVisitor(PhantomData))
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Localized<T> {
+    fn schema_name() -> String {
+        format!("Localized_{}", T::schema_name())
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let value = gen.subschema_for::<T>();
+        let by_locale = SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            object: Some(Box::new(ObjectValidation {
+                min_properties: Some(1),
+                additional_properties: Some(Box::new(value.clone())),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some("The same in every language, or a map from locales like \"en\" or \"pl\" \
+                                   to translations, the first of which is the default.".to_string()),
+                ..Default::default()
+            })),
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![value, by_locale.into()]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sword() -> Localized<String> {
+        serde_json::from_str(r#"{"en": "Sword", "pl-PL": "Miecz"}"#).unwrap()
+    }
+
+    #[test]
+    fn matches_locales_by_language() {
+        assert!(matches("pl-PL", "pl"));
+        assert!(matches("pl", "PL_pl"));
+        assert!(matches("en-GB", "en-US"));
+        assert!(!matches("pl", "en"));
+    }
+
+    #[test]
+    fn falls_back_to_the_first_language() {
+        let mut name = sword();
+        assert_eq!(name.locales(), vec!["en", "pl-PL"]);
+        assert_eq!(*name, "Sword");
+        name.select("PL-pl");
+        assert_eq!(*name, "Miecz");
+        name.select("pl");
+        assert_eq!(*name, "Miecz");
+        name.select("de");
+        assert_eq!(*name, "Sword");
+        assert_eq!(name.original(), "Sword");
+        assert!(name.has("Miecz"));
+    }
+
+    #[test]
+    fn prefers_the_exact_locale() {
+        let mut name: Localized<String> = serde_json::from_str(r#"{"en-US": "Color", "en-GB": "Colour"}"#).unwrap();
+        name.select("en-gb");
+        assert_eq!(*name, "Colour");
+        name.select("en");
+        assert_eq!(*name, "Color");
+    }
+
+    #[test]
+    fn reads_and_writes_plain_and_localized_values() {
+        let plain: Localized<Vec<String>> = serde_json::from_str(r#"["Hi.", "Bye."]"#).unwrap();
+        let mut plain_copy = plain.clone();
+        plain_copy.select("pl");
+        assert_eq!(*plain_copy, vec!["Hi.", "Bye."]);
+        assert!(plain.locales().is_empty());
+        assert_eq!(serde_json::to_string(&plain).unwrap(), r#"["Hi.","Bye."]"#);
+        assert_eq!(serde_json::to_string(&sword()).unwrap(), r#"{"en":"Sword","pl-PL":"Miecz"}"#);
+    }
+
+    #[test]
+    fn rejects_empty_locales() {
+        assert!(serde_json::from_str::<Localized<String>>("{}").is_err());
+        assert!(serde_json::from_str::<Localized<String>>(r#"{" ": "Sword"}"#).unwrap_err().to_string()
+            .contains("locales can't be empty"));
+    }
+}
@@ -1,65 +1,251 @@
 //#[macro_use]
+extern crate dirs;
 extern crate itertools;
 #[macro_use]
 extern crate lazy_static;
+extern crate libc;
 extern crate ncurses;
 extern crate regex;
+extern crate ron;
+extern crate schemars;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate serde_yaml;
+extern crate toml;
+extern crate zip;
 
+use std::path::{Path, PathBuf};
+
+use analysis::check_game;
 use parsing::parse_game;
-use playback::play_game;
+use playback::{play_game, PlayOptions};
+use replay::read_recording;
+use save::load_game;
 
-use crate::parsing::print_format;
+use crate::graph::{render_graph, GraphFormat};
+use crate::parsing::{convert_game, migrate_game, PACKAGE_ENTRY};
+use crate::schema::{format_reference, print_schema};
 
 #[macro_use]
 mod debug;
+mod analysis;
+mod diagnostics;
 mod misc;
 mod playback;
 mod game_components;
 mod game_state;
+mod graph;
+mod history;
+mod ids;
+mod include;
+mod locale;
+mod markup;
+mod migration;
 mod parsing;
+mod config;
 mod console;
+mod profile;
+mod replay;
+mod save;
+mod schema;
+mod script;
+mod template;
+mod transcript;
 
 fn main() {
     // TODO: replace this with a real arg parser
     let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} filename", args[0]);
+    const FORMAT_ARG: &str = "--format";
+    const LOAD_ARG: &str = "--load";
+    const RECORD_ARG: &str = "--record";
+    const REPLAY_ARG: &str = "--replay";
+    const TRANSCRIPT_ARG: &str = "--transcript";
+    const LANG_ARG: &str = "--lang";
+    const CONVERT_COMMAND: &str = "convert";
+    const CHECK_COMMAND: &str = "check";
+    const GRAPH_COMMAND: &str = "graph";
+    const MIGRATE_COMMAND: &str = "migrate";
+    const SCHEMA_COMMAND: &str = "schema";
+    const SCHEMA_INCLUDE_ARG: &str = "--include";
+    if args.len() < 2 {
+        eprintln!("Usage: {} filename [{} save-file] [{} file | {} file] [{} file] [{} locale]",
+                  args[0], LOAD_ARG, RECORD_ARG, REPLAY_ARG, TRANSCRIPT_ARG, LANG_ARG);
         std::process::exit(1);
     }
-    const FORMAT_ARG: &str = "--format";
-    const FORMAT_REGEN_ARG: &str = "-regen";
 
     if args[1] == "--help" {
         println!("\
         Welcome to Text Adventure Parser 3000!\n\
         To play a game, run:\n\
         {name} game-file.agf\n\
+        The game file can also be a plain JSON, TOML, YAML or RON file, a .story script\n\
+        written in the story script language, or - to read it from the standard input.\n\
+        An .agf package is a zip archive with the game stored as {entry}.json (or .toml, .yaml, .ron, .story).\n\
+        A game can be split into files listed in its \"include\" field, relative to the game file.\n\
+        To continue a saved game, run:\n\
+        {name} game-file.agf {load} save-file\n\
+        To record your key presses, or to replay a recording, run:\n\
+        {name} game-file.agf {record} recording.json\n\
+        {name} game-file.agf {replay} recording.json\n\
+        To write a transcript of your playthrough (.md files get Markdown), run:\n\
+        {name} game-file.agf {transcript} transcript.md\n\
+        To play a translated game in a given language, instead of picking one at the start, run:\n\
+        {name} game-file.agf {lang} pl\n\
+        To convert a game file to another format, picked by the extension, run:\n\
+        {name} {convert} game-file.json game-file.yaml\n\
+        To upgrade a game file and the files it includes to the current format, keeping backups, run:\n\
+        {name} {migrate} game-file.json\n\
+        To look for problems in a game, like stages that can't be reached or soft-locks, run:\n\
+        {name} {check} game-file.agf\n\
+        To draw the stages and options of a game as a Graphviz or Mermaid graph, run:\n\
+        {name} {graph} game-file.agf [{format} {graph_formats}]\n\
         To display game file format, run:\n\
         {name} {format}\n\
-        To force the format regeneration, run:\n\
-        {name} {format}{regen}\n\
+        To print a JSON Schema of game files, or of files they include, for your editor, run:\n\
+        {name} {schema} [{schema_include}]\n\
 
         Copyright © 2018-2019 Jacek Olczyk",
                  name = args[0],
+                 entry = PACKAGE_ENTRY,
                  format = FORMAT_ARG,
-                 regen = FORMAT_REGEN_ARG);
+                 schema = SCHEMA_COMMAND,
+                 schema_include = SCHEMA_INCLUDE_ARG,
+                 load = LOAD_ARG,
+                 record = RECORD_ARG,
+                 replay = REPLAY_ARG,
+                 transcript = TRANSCRIPT_ARG,
+                 lang = LANG_ARG,
+                 convert = CONVERT_COMMAND,
+                 check = CHECK_COMMAND,
+                 migrate = MIGRATE_COMMAND,
+                 graph = GRAPH_COMMAND,
+                 graph_formats = GraphFormat::NAMES);
         std::process::exit(0);
     }
 
     if args[1] == FORMAT_ARG {
-        print_format(false);
+        print!("{}", format_reference());
+        std::process::exit(0);
+    }
+    if args[1] == SCHEMA_COMMAND {
+        match &args[2..] {
+            [] => print_schema(false),
+            [arg] if arg == SCHEMA_INCLUDE_ARG => print_schema(true),
+            _ => {
+                eprintln!("Usage: {} {} [{}]", args[0], SCHEMA_COMMAND, SCHEMA_INCLUDE_ARG);
+                std::process::exit(1);
+            }
+        }
+        std::process::exit(0);
+    }
+    if args[1] == CONVERT_COMMAND {
+        if args.len() != 4 {
+            eprintln!("Usage: {} {} input-file output-file", args[0], CONVERT_COMMAND);
+            std::process::exit(1);
+        }
+        if let Err(err) = convert_game(&args[2], &args[3]) {
+            println!("Error converting game: {}", err);
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
+
+    if args[1] == MIGRATE_COMMAND {
+        if args.len() != 3 {
+            eprintln!("Usage: {} {} game-file", args[0], MIGRATE_COMMAND);
+            std::process::exit(1);
+        }
+        match migrate_game(&args[2]) {
+            Ok(report) => report.iter().for_each(|line| println!("{}", line)),
+            Err(err) => {
+                println!("Error migrating game: {}", err);
+                std::process::exit(1);
+            }
+        }
         std::process::exit(0);
     }
-    if args[1] == FORMAT_ARG.to_string() + FORMAT_REGEN_ARG {
-        print_format(true);
+    if args[1] == CHECK_COMMAND {
+        if args.len() != 3 {
+            eprintln!("Usage: {} {} game-file", args[0], CHECK_COMMAND);
+            std::process::exit(1);
+        }
+        match check_game(&args[2]) {
+            Ok(diagnostics) if diagnostics.is_empty() => println!("No problems found."),
+            Ok(diagnostics) => {
+                diagnostics.iter().for_each(|diagnostic| println!("{}", diagnostic));
+                println!("Found {} problem(s).", diagnostics.len());
+                std::process::exit(1);
+            }
+            Err(err) => {
+                println!("Error parsing game: {}", err);
+                std::process::exit(1);
+            }
+        }
         std::process::exit(0);
     }
 
-    let init_state = match parse_game(&args[1][..]) {
+    if args[1] == GRAPH_COMMAND {
+        let format = match &args[2..] {
+            [_] => Some(GraphFormat::Dot),
+            [_, arg, format] if arg == FORMAT_ARG => GraphFormat::from_name(format),
+            _ => None,
+        };
+        let format = format.unwrap_or_else(|| {
+            eprintln!("Usage: {} {} game-file [{} {}]", args[0], GRAPH_COMMAND, FORMAT_ARG, GraphFormat::NAMES);
+            std::process::exit(1);
+        });
+        match parse_game(&args[2]) {
+            Ok(game) => print!("{}", render_graph(&game, format)),
+            Err(err) => {
+                println!("Error parsing game: {}", err);
+                std::process::exit(1);
+            }
+        }
+        std::process::exit(0);
+    }
+
+    let mut save_file: Option<&str> = None;
+    let mut record_file: Option<&str> = None;
+    let mut replay_file: Option<&str> = None;
+    let mut transcript_file: Option<&str> = None;
+    let mut language: Option<&str> = None;
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        let target = match &arg[..] {
+            LOAD_ARG => &mut save_file,
+            RECORD_ARG => &mut record_file,
+            REPLAY_ARG => &mut replay_file,
+            TRANSCRIPT_ARG => &mut transcript_file,
+            LANG_ARG => &mut language,
+            _ => {
+                eprintln!("Unexpected argument \"{}\"! Run {} --help for usage.", arg, args[0]);
+                std::process::exit(1);
+            }
+        };
+        match rest.next() {
+            Some(value) if target.is_none() => *target = Some(value),
+            Some(_) => {
+                eprintln!("{} was given more than once!", arg);
+                std::process::exit(1);
+            }
+            None if arg == LANG_ARG => {
+                eprintln!("{} needs a locale, like en or pl!", arg);
+                std::process::exit(1);
+            }
+            None => {
+                eprintln!("{} needs a file name!", arg);
+                std::process::exit(1);
+            }
+        }
+    }
+    if record_file.is_some() && replay_file.is_some() {
+        eprintln!("Can't record and replay at the same time!");
+        std::process::exit(1);
+    }
+
+    let mut init_state = match parse_game(&args[1][..]) {
         Ok(st) => st,
         Err(err) => {
             println!("Error parsing game: {}", err);
@@ -67,5 +253,62 @@ fn main() {
         }
     };
 
-    play_game(init_state);
+    if let Some(language) = language {
+        let known = init_state.languages().iter().chain(init_state.metadata.language.iter())
+            .any(|known| locale::matches(known, language));
+        if !known {
+            match init_state.languages() {
+                [] => eprintln!("\"{}\" isn't translated to other languages!", markup::strip(init_state.get_name())),
+                languages => eprintln!("\"{}\" has no \"{}\" translation, it can be played in: {}.",
+                                       markup::strip(init_state.get_name()), language, languages.join(", ")),
+            }
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(save_file) = save_file {
+        if let Err(err) = load_game(&mut init_state, Path::new(save_file)) {
+            println!("Error loading save: {}", err);
+            return;
+        }
+    }
+
+    let recording = match replay_file.map(|path| read_recording(Path::new(path))) {
+        Some(Err(err)) => {
+            println!("Error loading recording: {}", err);
+            return;
+        }
+        Some(Ok(recording)) => Some(recording),
+        None => None,
+    };
+    let recorded_with_other_version = recording.as_ref()
+        .is_some_and(|recording| recording.game_hash != save::format_hash(init_state.source_hash));
+    // Sessions recorded after loading a save start from the state they were recorded in.
+    if let Some(start) = recording.as_ref().and_then(|recording| recording.start.clone()) {
+        if let Err(err) = init_state.restore(start) {
+            println!("Error loading recording: {}", err);
+            return;
+        }
+    }
+
+    let final_state = play_game(init_state, PlayOptions {
+        pick_save: save_file.is_none(),
+        record: record_file.map(PathBuf::from),
+        replay: recording,
+        transcript: transcript_file.map(PathBuf::from),
+        language: language.map(str::to_string),
+    });
+
+    if replay_file.is_some() {
+        if recorded_with_other_version {
+            println!("Warning: the recording was made with a different version of the game file.");
+        }
+        match final_state {
+            Some(ref state) if state.is_finished() =>
+                println!("Replay finished the game in stage \"{}\".", markup::strip(&state.get_current_stage().name)),
+            Some(ref state) =>
+                println!("Replay stopped in stage \"{}\".", markup::strip(&state.get_current_stage().name)),
+            None => println!("Replay stopped on the title screen."),
+        }
+    }
 }
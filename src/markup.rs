@@ -0,0 +1,295 @@
+//! Styling inside texts players see: `*bold*`, `_underlined_` and `[red]colored[/]`, in any of the colors
+//! in `Color::ALL`. `**`, `__` and `[[` show a single `*`, `_` and `[`, and a `[` that doesn't start
+//! a color is shown as it is. Styles end with the line.
+//!
+//! Markup is read when texts are compiled, see `template`. `Console` shows it through terminal attributes,
+//! everything else, like transcripts, gets the text without it.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::misc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+impl Color {
+    /// In the order of the curses color numbers.
+    pub const ALL: [Color; 8] = [
+        Color::Black, Color::Red, Color::Green, Color::Yellow, Color::Blue, Color::Magenta, Color::Cyan, Color::White,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Color::Black => "black",
+            Color::Red => "red",
+            Color::Green => "green",
+            Color::Yellow => "yellow",
+            Color::Blue => "blue",
+            Color::Magenta => "magenta",
+            Color::Cyan => "cyan",
+            Color::White => "white",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Color> {
+        Color::ALL.iter().cloned().find(|color| color.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Style {
+    pub bold: bool,
+    pub underline: bool,
+    pub color: Option<Color>,
+}
+
+impl Style {
+    /// The style of text shown inside text of the `outer` style, like an item name in a bold sentence.
+    pub fn within(self, outer: Style) -> Style {
+        Style {
+            bold: self.bold || outer.bold,
+            underline: self.underline || outer.underline,
+            color: self.color.or(outer.color),
+        }
+    }
+}
+
+/// A change of style, as written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mark {
+    /// `*`, which starts or ends bold text.
+    Bold,
+    /// `_`, which starts or ends underlined text.
+    Underline,
+    Color(Color),
+    /// `[/]`.
+    EndColor,
+}
+
+impl Mark {
+    pub fn apply(self, style: &mut Style) {
+        match self {
+            Mark::Bold => style.bold = !style.bold,
+            Mark::Underline => style.underline = !style.underline,
+            Mark::Color(color) => style.color = Some(color),
+            Mark::EndColor => style.color = None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Piece {
+    Text(String),
+    Mark(Mark),
+}
+
+/// Splits text into plain text and marks.
+pub fn parse(text: &str) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let (mark, length) = match c {
+            '*' | '_' | '[' if rest[1..].starts_with(c) => {
+                plain.push(c);
+                rest = &rest[2..];
+                continue;
+            }
+            '*' => (Mark::Bold, 1),
+            '_' => (Mark::Underline, 1),
+            '[' if rest.starts_with("[/]") => (Mark::EndColor, 3),
+            '[' => match rest.find(']').and_then(|end| Color::from_name(&rest[1..end]).map(|color| (color, end))) {
+                Some((color, end)) => (Mark::Color(color), end + 1),
+                None => {
+                    plain.push(c);
+                    rest = &rest[1..];
+                    continue;
+                }
+            },
+            _ => {
+                plain.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+        };
+        if !plain.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(&mut plain)));
+        }
+        pieces.push(Piece::Mark(mark));
+        rest = &rest[length..];
+    }
+    if !plain.is_empty() {
+        pieces.push(Piece::Text(plain));
+    }
+    pieces
+}
+
+/// Styled text from text with markup that isn't a template, like a name, shown within the `outer` style.
+pub fn spans(text: &str, outer: Style) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut style = Style::default();
+    for piece in parse(text) {
+        match piece {
+            Piece::Text(text) => push(&mut spans, &text, style.within(outer)),
+            Piece::Mark(mark) => mark.apply(&mut style),
+        }
+    }
+    spans
+}
+
+/// The text without its markup.
+pub fn strip(text: &str) -> String {
+    parse(text).into_iter().filter_map(|piece| match piece {
+        Piece::Text(text) => Some(text),
+        Piece::Mark(_) => None,
+    }).collect()
+}
+
+/// The text with `*`, `_` and `[` doubled, so it's shown as it is.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if let '*' | '_' | '[' = c {
+            escaped.push(c);
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A part of a shown text, all in one style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+/// Adds text at the end, in the last span if it's of the same style.
+pub fn push(spans: &mut Vec<Span>, text: &str, style: Style) {
+    match spans.last_mut() {
+        _ if text.is_empty() => {}
+        Some(last) if last.style == style => last.text.push_str(text),
+        _ => spans.push(Span { text: text.to_string(), style }),
+    }
+}
+
+/// The text without styles.
+pub fn plain(spans: &[Span]) -> String {
+    spans.iter().map(|span| &span.text[..]).collect()
+}
+
+/// Breaks styled text into lines at most `width` columns wide, at whitespace where it can,
+/// and between the characters of words longer than a line. Whitespace between words becomes a single space,
+/// whitespace at the start is kept.
+pub fn wrap(spans: &[Span], width: usize) -> Vec<Vec<Span>> {
+    let width = width.max(1);
+    // Words and the whitespace between them, each possibly in many styles.
+    let mut tokens: Vec<(bool, Vec<Span>)> = Vec::new();
+    for span in spans {
+        let mut rest = &span.text[..];
+        while let Some(c) = rest.chars().next() {
+            let space = c.is_whitespace();
+            let end = rest.find(|c: char| c.is_whitespace() != space).unwrap_or(rest.len());
+            match tokens.last_mut() {
+                Some((last_space, token)) if *last_space == space => push(token, &rest[..end], span.style),
+                _ => tokens.push((space, vec![Span { text: rest[..end].to_string(), style: span.style }])),
+            }
+            rest = &rest[end..];
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut line: Vec<Span> = Vec::new();
+    let mut line_width = 0;
+    let mut gap: Option<Span> = None;
+    for (i, (space, token)) in tokens.into_iter().enumerate() {
+        if space {
+            gap = match i {
+                0 => Some(Span { text: plain(&token), style: token[0].style }),
+                _ => Some(Span { text: " ".to_string(), style: token[0].style }),
+            };
+            continue;
+        }
+        let word_width = misc::str_width(&plain(&token));
+        let gap_width = gap.as_ref().map_or(0, |gap| misc::str_width(&gap.text));
+        if line_width > 0 && line_width + gap_width + word_width > width {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+            gap = None;
+        }
+        if let Some(gap) = gap.take() {
+            line_width += misc::str_width(&gap.text);
+            push(&mut line, &gap.text, gap.style);
+        }
+        for span in token {
+            for grapheme in span.text.graphemes(true) {
+                let grapheme_width = misc::grapheme_width(grapheme);
+                if line_width + grapheme_width > width && line_width > 0 {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0;
+                }
+                line_width += grapheme_width;
+                push(&mut line, grapheme, span.style);
+            }
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(bold: bool, underline: bool, color: Option<Color>) -> Style {
+        Style { bold, underline, color }
+    }
+
+    #[test]
+    fn parses_marks_and_escapes() {
+        assert_eq!(parse("*a* [red]b[/] c__d"), vec![
+            Piece::Mark(Mark::Bold), Piece::Text("a".to_string()), Piece::Mark(Mark::Bold), Piece::Text(" ".to_string()),
+            Piece::Mark(Mark::Color(Color::Red)), Piece::Text("b".to_string()), Piece::Mark(Mark::EndColor),
+            Piece::Text(" c_d".to_string()),
+        ]);
+        assert_eq!(strip("[[red] and [not a color] **"), "[red] and [not a color] *");
+    }
+
+    #[test]
+    fn escaped_text_is_shown_as_it_is() {
+        for text in ["5 * 3", "snake_case", "[red]", "**", "plain"] {
+            assert_eq!(strip(&escape(text)), text);
+        }
+    }
+
+    #[test]
+    fn styles_nest_within_the_outer_style() {
+        let bold = style(true, false, None);
+        assert_eq!(spans("a _b_ [blue]c", bold), vec![
+            Span { text: "a ".to_string(), style: bold },
+            Span { text: "b".to_string(), style: style(true, true, None) },
+            Span { text: " ".to_string(), style: bold },
+            Span { text: "c".to_string(), style: style(true, false, Some(Color::Blue)) },
+        ]);
+    }
+
+    #[test]
+    fn wraps_at_whitespace_and_inside_long_words() {
+        let lines = wrap(&spans("one *two* three", Style::default()), 9);
+        assert_eq!(lines.iter().map(|line| plain(line)).collect::<Vec<_>>(), vec!["one two", "three"]);
+        assert!(lines[0].iter().any(|span| span.text == "two" && span.style.bold));
+        let lines = wrap(&spans("abcdefgh", Style::default()), 3);
+        assert_eq!(lines.iter().map(|line| plain(line)).collect::<Vec<_>>(), vec!["abc", "def", "gh"]);
+    }
+}
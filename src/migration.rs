@@ -0,0 +1,178 @@
+//! Game files say which version of the format they were written for in `format_version`.
+//! Older documents are upgraded one version at a time before they're deserialized,
+//! so a change to the format never silently changes what an existing game means.
+//!
+//! To change the format: bump `FORMAT_VERSION` and add a step to `MIGRATIONS` that rewrites
+//! a document of the previous version, as a JSON value, into the new one.
+
+use serde_json::Value;
+
+use crate::markup;
+
+/// The version of the format this build reads and writes.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Rewrites a document, as a JSON value, from one format version to the next.
+pub type Step = fn(&mut Value) -> Result<(), String>;
+
+pub struct Migration {
+    /// What changed, shown by `advgame migrate`.
+    pub summary: &'static str,
+    /// Rewrites a document of the previous version, or nothing if only the version number changed,
+    /// in which case documents of the previous version are read as they are.
+    pub apply: Option<Step>,
+}
+
+/// Step `i` upgrades documents of version `i` to version `i + 1`.
+/// Files from before versions have no `format_version` and count as version 0.
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize] = [
+    Migration {
+        summary: "files say which format version they use",
+        apply: None,
+    },
+    Migration {
+        summary: "texts can be styled and show a single $ for $$, so *, _, [ and $$ in existing texts are escaped",
+        apply: Some(escape_texts),
+    },
+];
+
+/// The steps that upgrade a document of the given version to `FORMAT_VERSION`.
+pub fn pending(version: u32) -> Result<&'static [Migration], String> {
+    MIGRATIONS.get(version as usize..).ok_or(format!(
+        "the file uses format version {}, but this version of advgame only knows versions up to {}",
+        version, FORMAT_VERSION
+    ))
+}
+
+/// Whether any of the steps rewrites documents, so they have to go through `migrate`.
+pub fn rewrites(steps: &[Migration]) -> bool {
+    steps.iter().any(|step| step.apply.is_some())
+}
+
+/// Upgrades a document of the given version to `FORMAT_VERSION`.
+pub fn migrate(document: &mut Value, version: u32) -> Result<(), String> {
+    for (from, step) in pending(version)?.iter().enumerate() {
+        if let Some(apply) = step.apply {
+            apply(document).map_err(|message| format!(
+                "could not upgrade from format version {}: {}", version as usize + from, message
+            ))?;
+        }
+    }
+    if let Value::Object(fields) = document {
+        fields.insert("format_version".to_string(), Value::from(FORMAT_VERSION));
+    }
+    Ok(())
+}
+
+/// Runs `escape` on every text in a value, which is a text, a list of them, or either by language.
+fn escape_all(value: Option<&mut Value>, escape: fn(&str) -> String) {
+    match value {
+        Some(Value::String(text)) => *text = escape(text),
+        Some(Value::Array(values)) => values.iter_mut().for_each(|value| escape_all(Some(value), escape)),
+        Some(Value::Object(values)) => values.values_mut().for_each(|value| escape_all(Some(value), escape)),
+        _ => {}
+    }
+}
+
+/// Escapes a text with placeholders, which version 1 ended at the first `}`, leaving them as they are.
+/// A `$` that doesn't start one is doubled, as is a `$$`, which showed both.
+fn escape_template(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(dollar) = rest.find('$') {
+        escaped.push_str(&markup::escape(&rest[..dollar]));
+        rest = &rest[dollar..];
+        match rest.find('}').filter(|_| rest.starts_with("${")) {
+            Some(end) => {
+                escaped.push_str(&rest[..=end]);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                escaped.push_str("$$");
+                rest = &rest[1..];
+            }
+        }
+    }
+    escaped.push_str(&markup::escape(rest));
+    escaped
+}
+
+/// The entries of a list in the document, like its stages.
+fn entries<'a>(document: &'a mut Value, list: &str) -> impl Iterator<Item=&'a mut Value> {
+    document.get_mut(list).and_then(Value::as_array_mut).into_iter().flatten()
+}
+
+/// Version 2 reads markup in texts players see, see `markup`, and `$$` in texts with placeholders,
+/// see `template`. Texts of older versions are escaped so they're shown as they were.
+fn escape_texts(document: &mut Value) -> Result<(), String> {
+    escape_all(document.get_mut("name"), markup::escape);
+    if let Some(metadata) = document.get_mut("metadata") {
+        escape_all(metadata.get_mut("description"), markup::escape);
+        escape_all(metadata.get_mut("content_warnings"), markup::escape);
+        escape_all(metadata.get_mut("intro"), escape_template);
+    }
+    for stat in entries(document, "stats") {
+        escape_all(stat.get_mut("name"), markup::escape);
+    }
+    for stage in entries(document, "stages") {
+        escape_all(stage.get_mut("name"), markup::escape);
+        escape_all(stage.get_mut("text"), escape_template);
+        for option in entries(stage, "options") {
+            escape_all(option.get_mut("text"), escape_template);
+        }
+    }
+    for item in entries(document, "items") {
+        escape_all(item.get_mut("name"), escape_template);
+    }
+    for achievement in entries(document, "achievements") {
+        escape_all(achievement.get_mut("name"), markup::escape);
+        escape_all(achievement.get_mut("description"), markup::escape);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn escapes_texts_outside_placeholders() {
+        assert_eq!(escape_template("5 * 3 is ${stat.Snake_case}, $$5 or $x [red]"),
+                   "5 ** 3 is ${stat.Snake_case}, $$$$5 or $$x [[red]");
+        assert_eq!(escape_template("${stat.Gold"), "$${stat.Gold");
+        assert_eq!(escape_template("$${item.0}"), "$$${item.0}");
+    }
+
+    #[test]
+    fn escapes_every_text_of_a_document() {
+        let mut document = json!({
+            "name": "My_game",
+            "metadata": {"intro": ["a_b"], "content_warnings": {"en": ["x_y"], "pl": ["x*y"]}},
+            "stats": [{"id": "some_id", "name": "Hit_points"}],
+            "stages": [{"index": 0, "name": "[Cave]", "text": {"en": ["$$1"]}, "options": [{"text": ["a*b"]}]}],
+            "items": [{"name": "Sword_$"}],
+            "achievements": [{"name": "A_B", "description": "c*d"}],
+        });
+        migrate(&mut document, 1).unwrap();
+        assert_eq!(document, json!({
+            "name": "My__game",
+            "metadata": {"intro": ["a__b"], "content_warnings": {"en": ["x__y"], "pl": ["x**y"]}},
+            "stats": [{"id": "some_id", "name": "Hit__points"}],
+            "stages": [{"index": 0, "name": "[[Cave]", "text": {"en": ["$$$$1"]}, "options": [{"text": ["a**b"]}]}],
+            "items": [{"name": "Sword__$$"}],
+            "achievements": [{"name": "A__B", "description": "c**d"}],
+            "format_version": FORMAT_VERSION,
+        }));
+    }
+
+    #[test]
+    fn refuses_newer_versions() {
+        assert!(pending(FORMAT_VERSION).unwrap().is_empty());
+        assert!(rewrites(pending(0).unwrap()));
+        assert_eq!(migrate(&mut json!({}), FORMAT_VERSION + 1).unwrap_err(), format!(
+            "the file uses format version {}, but this version of advgame only knows versions up to {}",
+            FORMAT_VERSION + 1, FORMAT_VERSION
+        ));
+    }
+}
@@ -1,6 +1,28 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+/// How many terminal columns a grapheme cluster takes: two for wide characters, like most CJK and emoji,
+/// none for control characters.
+pub fn grapheme_width(grapheme: &str) -> usize {
+    // Marks combined with the first character don't take up space of their own,
+    // but a variation selector turns text symbols into wide emoji.
+    let first = grapheme.chars().next().and_then(|c| c.width()).unwrap_or(0);
+    if grapheme.contains('\u{FE0F}') { first.max(2) } else { first }
+}
+
+/// How many terminal columns the text takes.
+pub fn str_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
 
 pub fn max_str_len(v: &Vec<String>) -> usize {
-    v.iter().max_by(|text1, text2| {
-        text1.len().cmp(&text2.len())
-    }).unwrap_or(&String::new()).len()
-}
\ No newline at end of file
+    v.iter().map(|text| str_width(text)).max().unwrap_or(0)
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` it is stable between Rust versions,
+/// so it can be written to files.
+pub fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(PRIME))
+}
@@ -1,58 +1,764 @@
+use std::fmt;
+use std::fs;
 use std::fs::File;
-use std::io::{Error as IOError, Read};
-use std::io::ErrorKind;
-use std::path::Path;
-use std::process::Command;
+use std::io::{Cursor, Error as IOError, Read};
+use std::path::{Path, PathBuf};
 
+use regex::Regex;
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::diagnostics::{self, Diagnostic, Source};
 use crate::game_state::GameState;
+use crate::ids::Symbols;
+use crate::include::{GamePart, Merger};
+use crate::migration::{self, FORMAT_VERSION};
+use crate::misc;
+use crate::script::{self, ScriptError};
+
+/// Formats a game document can be written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameFormat {
+    Json,
+    Toml,
+    Yaml,
+    Ron,
+    /// The story script language, see `script`. Games can be read from it, but not written.
+    Script,
+}
+
+pub const ALL_FORMATS: [GameFormat; 5] =
+    [GameFormat::Json, GameFormat::Toml, GameFormat::Yaml, GameFormat::Ron, GameFormat::Script];
+
+impl fmt::Display for GameFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameFormat::Json => write!(f, "JSON"),
+            GameFormat::Toml => write!(f, "TOML"),
+            GameFormat::Yaml => write!(f, "YAML"),
+            GameFormat::Ron => write!(f, "RON"),
+            GameFormat::Script => write!(f, "story script"),
+        }
+    }
+}
+
+impl GameFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GameFormat::Json => "json",
+            GameFormat::Toml => "toml",
+            GameFormat::Yaml => "yaml",
+            GameFormat::Ron => "ron",
+            GameFormat::Script => "story",
+        }
+    }
+
+    pub fn from_extension(path: &str) -> Option<GameFormat> {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Some(GameFormat::Json),
+            Some("toml") => Some(GameFormat::Toml),
+            Some("yaml") | Some("yml") => Some(GameFormat::Yaml),
+            Some("ron") => Some(GameFormat::Ron),
+            Some("story") => Some(GameFormat::Script),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file couldn't be read.
+    Io { path: String, error: IOError },
+    /// The content doesn't look like any supported game format.
+    UnknownFormat { path: String },
+    /// The `.agf` package is broken or doesn't contain a game.
+    Package { path: String, message: String },
+    /// The document isn't well-formed or doesn't follow the game format.
+    Syntax { path: String, format: GameFormat, line: usize, column: usize, message: String },
+    /// The document was written for a format version that can't be read or upgraded.
+    Version { path: String, message: String },
+    /// An included file can't be merged into the game.
+    Include { path: String, message: String },
+    /// The story script has mistakes, all of them are listed.
+    Script { path: String, errors: Vec<ScriptError> },
+    /// The game was read, but doesn't make sense, e.g. an option points to an inexistent stage.
+    Invalid { path: String, diagnostics: Vec<Diagnostic> },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io { path, error } =>
+                write!(f, "Could not read \"{}\": {}", path, error),
+            LoadError::UnknownFormat { path } =>
+                write!(f, "\"{}\" is neither a game file nor an .agf package!", path),
+            LoadError::Package { path, message } =>
+                write!(f, "Broken .agf package \"{}\": {}", path, message),
+            LoadError::Syntax { path, format, line, column, message } =>
+                write!(f, "Error while parsing the {} file \"{}\", line {}:{}\n{}\n",
+                       format, path, line, column, message),
+            LoadError::Version { path, message } =>
+                write!(f, "Could not load \"{}\": {}", path, message),
+            LoadError::Include { path, message } =>
+                write!(f, "Could not include \"{}\": {}", path, message),
+            LoadError::Script { path, errors } => {
+                writeln!(f, "Found {} error(s) in the story script \"{}\":", errors.len(), path)?;
+                errors.iter().try_for_each(|error| writeln!(f, "{}", error))
+            }
+            LoadError::Invalid { path, diagnostics } => {
+                write!(f, "Found {} problem(s) in \"{}\":", diagnostics.len(), path)?;
+                diagnostics.iter().try_for_each(|diagnostic| write!(f, "\n{}", diagnostic))
+            }
+        }
+    }
+}
+
+/// The path that makes the loader read standard input.
+pub const STDIN_PATH: &str = "-";
+
+/// The name, without the extension, of the game document inside an `.agf` package.
+pub const PACKAGE_ENTRY: &str = "game";
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const UTF8_BOM: &str = "\u{feff}";
+
+/// Reads the whole file, or standard input if the path is `-`.
+pub fn read_source(path: &str) -> Result<Vec<u8>, LoadError> {
+    let mut buf = Vec::new();
+    let result = if path == STDIN_PATH {
+        std::io::stdin().read_to_end(&mut buf)
+    } else {
+        File::open(Path::new(path)).and_then(|mut file| file.read_to_end(&mut buf))
+    };
+    result.map(|_| buf).map_err(|error| LoadError::Io { path: path.to_string(), error })
+}
+
+/// Guesses the format of a game document from its first meaningful line. Story scripts, whose first lines
+/// look like YAML, are told by their `===` stage headers, unless the document reads fine in the format
+/// its first line points to, like YAML with such a line in a multiline text.
+pub fn detect_format(text: &str) -> Option<GameFormat> {
+    let structured = detect_data_format(text);
+    let script = text.lines().any(|line| line.starts_with("==="));
+    match structured {
+        Some(format) if !script || read_as::<IgnoredAny>("", text, format).is_ok() => Some(format),
+        _ if script => Some(GameFormat::Script),
+        _ => None,
+    }
+}
+
+fn detect_data_format(text: &str) -> Option<GameFormat> {
+    let line = text.trim_start_matches(UTF8_BOM).lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with("//"))?;
+    let key_end = line.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '"'));
+    if line.starts_with('{') {
+        Some(GameFormat::Json)
+    } else if line.starts_with('(') || line.starts_with("/*") || line.starts_with("#![") {
+        Some(GameFormat::Ron)
+    } else if line.starts_with("---") || line.starts_with("- ") {
+        Some(GameFormat::Yaml)
+    } else if line.starts_with('[') {
+        Some(GameFormat::Toml)
+    } else {
+        match key_end.map(|end| line[end..].trim_start()) {
+            Some(rest) if rest.starts_with('=') => Some(GameFormat::Toml),
+            Some(rest) if rest.starts_with(':') => Some(GameFormat::Yaml),
+            Some(rest) if rest.starts_with('(') => Some(GameFormat::Ron),
+            _ => None,
+        }
+    }
+}
+
+/// Turns a byte offset into a line and a column, both counted from 1.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+/// Deserializes a document in one of the data formats, which story scripts are not.
+fn read_as<T: DeserializeOwned>(path: &str, text: &str, format: GameFormat) -> Result<T, LoadError> {
+    let text = text.trim_start_matches(UTF8_BOM);
+    let syntax_error = |line: usize, column: usize, message: String| LoadError::Syntax {
+        path: path.to_string(),
+        format,
+        line,
+        column,
+        message,
+    };
+    match format {
+        GameFormat::Json => serde_json::from_str::<T>(text)
+            .map_err(|err| syntax_error(err.line(), err.column(), err.to_string())),
+        GameFormat::Toml => toml::from_str::<T>(text).map_err(|err| {
+            let (line, column) = err.span().map_or((0, 0), |span| line_column(text, span.start));
+            syntax_error(line, column, err.message().to_string())
+        }),
+        GameFormat::Yaml => serde_yaml::from_str::<T>(text).map_err(|err| {
+            let (line, column) = err.location().map_or((0, 0), |location| (location.line(), location.column()));
+            syntax_error(line, column, err.to_string())
+        }),
+        GameFormat::Ron => ron::de::from_str::<T>(text)
+            .map_err(|err| syntax_error(err.position.line, err.position.col, err.to_string())),
+        GameFormat::Script => Err(LoadError::Include {
+            path: path.to_string(),
+            message: "story scripts can't be included in other games".to_string(),
+        }),
+    }
+}
+
+/// Only the format version of a document and the files it includes, everything else in it is ignored.
+#[derive(Deserialize)]
+struct Versioned {
+    #[serde(default)]
+    format_version: u32,
+    #[serde(default)]
+    include: Vec<String>,
+}
+
+/// Reads a document as a value to upgrade, see `migration`. RON enums can't be read without knowing
+/// their type, so RON documents are read as a `T` first, which works while the steps they need
+/// only rewrite texts.
+fn read_value<T: DeserializeOwned + Serialize>(path: &str, text: &str, format: GameFormat) -> Result<Value, LoadError> {
+    if format != GameFormat::Ron {
+        return read_as(path, text, format);
+    }
+    serde_json::to_value(read_as::<T>(path, text, format)?)
+        .map_err(|err| LoadError::Version { path: path.to_string(), message: err.to_string() })
+}
+
+/// Reads a document of any format version, upgrading it first if needed, see `migration`.
+fn deserialize<T: DeserializeOwned + Serialize>(path: &str, text: &str, format: GameFormat) -> Result<T, LoadError> {
+    let version = read_as::<Versioned>(path, text, format)?.format_version;
+    let version_error = |message: String| LoadError::Version { path: path.to_string(), message };
+    let steps = migration::pending(version).map_err(version_error)?;
+    if !migration::rewrites(steps) {
+        return read_as(path, text, format);
+    }
+    let mut document = read_value::<T>(path, text, format)?;
+    migration::migrate(&mut document, version).map_err(version_error)?;
+    serde_json::from_value(document).map_err(|err| version_error(format!(
+        "after upgrading from format version {}: {}", version, err
+    )))
+}
+
+/// Parses a game document without post processing, so ids are still the ones from the file.
+pub fn parse_document(path: &str, text: &str, format: GameFormat) -> Result<GameState, LoadError> {
+    match format {
+        GameFormat::Script => script::compile(text.trim_start_matches(UTF8_BOM))
+            .map_err(|errors| LoadError::Script { path: path.to_string(), errors }),
+        _ => {
+            let mut state: GameState = deserialize(path, text, format)?;
+            state.format_version = FORMAT_VERSION;
+            Ok(state)
+        }
+    }
+}
+
+/// Writes a game, or a file it includes, that wasn't post processed yet in the given format.
+pub fn write_document<T: Serialize>(state: &T, format: GameFormat) -> Result<String, String> {
+    match format {
+        GameFormat::Json => serde_json::to_string_pretty(state).map_err(|err| err.to_string()),
+        GameFormat::Toml => toml::to_string_pretty(state).map_err(|err| err.to_string()),
+        GameFormat::Yaml => serde_yaml::to_string(state).map_err(|err| err.to_string()),
+        GameFormat::Ron => ron::ser::to_string_pretty(state, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string()),
+        GameFormat::Script => Err("Games can't be written as story scripts.".to_string()),
+    }
+}
+
+fn open_package<'a>(path: &str, bytes: &'a [u8]) -> Result<zip::ZipArchive<Cursor<&'a [u8]>>, LoadError> {
+    zip::ZipArchive::new(Cursor::new(bytes))
+        .map_err(|err| LoadError::Package { path: path.to_string(), message: err.to_string() })
+}
+
+/// Extracts the game document from an `.agf` package, which is a zip archive
+/// with the game stored as `game.json`, `game.toml`, `game.yaml`, `game.ron` or `game.story`.
+fn unpack(path: &str, bytes: &[u8]) -> Result<(String, GameFormat), LoadError> {
+    let package_error = |message: String| LoadError::Package { path: path.to_string(), message };
+    let mut archive = open_package(path, bytes)?;
+    for format in ALL_FORMATS.iter() {
+        let name = format!("{}.{}", PACKAGE_ENTRY, format.extension());
+        if let Ok(mut entry) = archive.by_name(&name) {
+            let mut text = String::new();
+            entry.read_to_string(&mut text).map_err(|err| package_error(err.to_string()))?;
+            return Ok((text, *format));
+        }
+    }
+    Err(package_error(format!("there is no {}.json inside", PACKAGE_ENTRY)))
+}
+
+/// Where the files included by a game are looked up.
+enum Origin<'a> {
+    /// Next to the game file, or in the current directory for standard input.
+    Directory(PathBuf),
+    /// Inside the `.agf` package the game came from, relative to its root.
+    Package { path: &'a str, bytes: &'a [u8], dir: PathBuf },
+}
+
+impl<'a> Origin<'a> {
+    /// Finds an included file, returning the name it's reported with, its raw bytes
+    /// and where the files it includes are looked up.
+    fn open(&self, name: &str) -> Result<(String, Vec<u8>, Origin<'a>), LoadError> {
+        match self {
+            Origin::Directory(dir) => {
+                let path = dir.join(name);
+                let display = path.to_string_lossy().to_string();
+                let bytes = read_source(&display)?;
+                let parent = path.parent().map_or(PathBuf::new(), Path::to_path_buf);
+                Ok((display, bytes, Origin::Directory(parent)))
+            }
+            Origin::Package { path, bytes, dir } => {
+                let entry_path = dir.join(name);
+                let entry_name = entry_path.to_string_lossy().to_string();
+                let mut archive = open_package(path, bytes)?;
+                let mut entry = archive.by_name(&entry_name).map_err(|err| LoadError::Package {
+                    path: path.to_string(),
+                    message: format!("can't include \"{}\": {}", entry_name, err),
+                })?;
+                let mut entry_bytes = Vec::new();
+                entry.read_to_end(&mut entry_bytes).map_err(|err| LoadError::Package {
+                    path: path.to_string(),
+                    message: err.to_string(),
+                })?;
+                let parent = entry_path.parent().map_or(PathBuf::new(), Path::to_path_buf);
+                let display = format!("{}:{}", path, entry_name);
+                Ok((display, entry_bytes, Origin::Package { path, bytes, dir: parent }))
+            }
+        }
+    }
+}
+
+/// Merges included files into a game, keeping what's needed to tell versions apart and to locate problems.
+struct Includer {
+    merger: Merger,
+    /// The files being merged, the innermost last, as canonical paths and the names they're shown with.
+    chain: Vec<(String, String)>,
+    /// Canonical paths of the files merged so far.
+    merged: Vec<String>,
+    /// The bytes of the game file and all included files.
+    bytes: Vec<u8>,
+    sources: Vec<Source>,
+}
 
-pub fn open_json_file(filename: &str) -> Result<String, IOError> {
-    const EXTENSION: &str = ".json";
-    if !filename.ends_with(EXTENSION) {
-        return Err(IOError::new(ErrorKind::InvalidInput, format!("\"{}\" is not a JSON file!", filename)));
+impl Includer {
+    /// Merges all included files into the game, depth first.
+    /// A file that was already merged is skipped, but including a file from itself,
+    /// directly or not, is an error.
+    fn merge_all(&mut self, state: &mut GameState, includes: &[String], origin: &Origin) -> Result<(), LoadError> {
+        for name in includes {
+            let (display, bytes, inner_origin) = origin.open(name)?;
+            let key = fs::canonicalize(&display).map_or(display.clone(), |path| path.to_string_lossy().to_string());
+            if self.chain.iter().any(|(file, _)| *file == key) {
+                let cycle = self.chain.iter().skip_while(|(file, _)| *file != key).map(|(_, name)| name.clone())
+                    .chain(std::iter::once(display.clone())).collect::<Vec<_>>().join(" -> ");
+                return Err(LoadError::Include { path: display, message: format!("include cycle {}", cycle) });
+            }
+            if self.merged.contains(&key) {
+                continue;
+            }
+            let text = String::from_utf8(bytes.clone())
+                .map_err(|_| LoadError::UnknownFormat { path: display.clone() })?;
+            let format = GameFormat::from_extension(&display).or_else(|| detect_format(&text))
+                .ok_or_else(|| LoadError::UnknownFormat { path: display.clone() })?;
+            let part: GamePart = deserialize(&display, &text, format)?;
+            self.bytes.extend_from_slice(&bytes);
+            let before = diagnostics::list_lengths(state);
+            self.merger.merge(state, GamePart { include: Vec::new(), ..part }, &display);
+            self.sources.push(Source::new(&display, &text, format, state, &before));
+            self.chain.push((key.clone(), display.clone()));
+            self.merge_all(state, &part.include, &inner_origin)?;
+            self.chain.pop();
+            self.merged.push(key);
+        }
+        Ok(())
     }
-    let path = Path::new(filename);
-    let mut ret = String::new();
-    let file_size = File::open(path)?.read_to_string(&mut ret)?;
-    ret = ret[..file_size].to_string();
-    Ok(ret)
 }
 
-pub fn parse_game(filename: &str) -> Result<GameState, String> {
-    let file = match open_json_file(filename) {
-        Ok(f) => f,
-        Err(err) => return Err(err.to_string())
+/// Tells the format of a game document and extracts it from `.agf` packages.
+fn decode(path: &str, display_path: &str, bytes: &[u8]) -> Result<(String, GameFormat), LoadError> {
+    let unknown_format = || LoadError::UnknownFormat { path: display_path.to_string() };
+    if bytes.starts_with(ZIP_MAGIC) {
+        unpack(display_path, bytes)
+    } else {
+        let text = String::from_utf8(bytes.to_vec()).map_err(|_| unknown_format())?;
+        let format = GameFormat::from_extension(path).or_else(|| detect_format(&text)).ok_or_else(unknown_format)?;
+        Ok((text, format))
+    }
+}
+
+fn display_path(path: &str) -> &str {
+    if path == STDIN_PATH { "<stdin>" } else { path }
+}
+
+/// Reads a game document from a file, an `.agf` package or standard input, without post processing it
+/// or merging the files it includes. The format is taken from the extension of known game files,
+/// and recognized by the content otherwise. Also returns the raw bytes that were read.
+pub fn read_document(path: &str) -> Result<(GameState, Vec<u8>), LoadError> {
+    let bytes = read_source(path)?;
+    let (text, format) = decode(path, display_path(path), &bytes)?;
+    let state = parse_document(display_path(path), &text, format)?;
+    Ok((state, bytes))
+}
+
+/// Reads a game with everything it includes, without post processing it.
+/// Also returns the files it was read from, to locate problems with `diagnostics::locate`,
+/// and the ids included files define again.
+pub fn read_game(path: &str) -> Result<(GameState, Vec<Source>, Vec<Diagnostic>), LoadError> {
+    let bytes = read_source(path)?;
+    let display_path = display_path(path);
+    let (text, format) = decode(path, display_path, &bytes)?;
+    let mut state = parse_document(display_path, &text, format)?;
+    let sources = vec![Source::new(display_path, &text, format, &state, &[0; 4])];
+    if state.include.is_empty() {
+        state.source_hash = misc::fnv1a_hash(&bytes);
+        return Ok((state, sources, Vec::new()));
+    }
+    let mut includer = Includer {
+        merger: Merger::new(&state, display_path),
+        chain: vec![(fs::canonicalize(path).map_or(path.to_string(), |path| path.to_string_lossy().to_string()),
+                     display_path.to_string())],
+        merged: Vec::new(),
+        bytes: bytes.clone(),
+        sources,
     };
-    match serde_json::from_str::<GameState>(&file) {
-        Ok(st) => st.post_process(),
-        Err(err) => Err(format!("Error while parsing the JSON file, line {}:{}\n{}\n",
-                                err.line(), err.column(), err
-        ))
-    }
-}
-
-pub fn print_format(force_regen: bool) {
-    const FORMAT_FILE: &str = "format.txt";
-    let path = Path::new(FORMAT_FILE);
-    if force_regen || !path.exists() {
-        let command = "python3 create_format.py";
-        if cfg!(target_os = "windows") {
-            Command::new("cmd")
-                .args(&["/C", command])
-                .output()
-                .expect("failed to execute process")
+    let origin = if bytes.starts_with(ZIP_MAGIC) {
+        Origin::Package { path: display_path, bytes: &bytes, dir: PathBuf::new() }
+    } else if path == STDIN_PATH {
+        Origin::Directory(PathBuf::new())
+    } else {
+        Origin::Directory(Path::new(path).parent().map_or(PathBuf::new(), Path::to_path_buf))
+    };
+    let includes = std::mem::take(&mut state.include);
+    includer.merge_all(&mut state, &includes, &origin)?;
+    state.include = includes;
+    state.source_hash = misc::fnv1a_hash(&includer.bytes);
+    Ok((state, includer.sources, includer.merger.collisions))
+}
+
+/// Loads a game with everything it includes and gets it ready to be played.
+pub fn parse_game(path: &str) -> Result<GameState, LoadError> {
+    let (state, sources, mut found) = read_game(path)?;
+    let symbols = Symbols::new(&state);
+    // Ids included files define again are used twice in the merged game, so validation fails on them too.
+    state.post_process(&symbols).map_err(|diagnostics| {
+        diagnostics::add_new(&mut found, diagnostics);
+        diagnostics::locate(&mut found, &sources);
+        LoadError::Invalid { path: display_path(path).to_string(), diagnostics: found }
+    })
+}
+
+/// Rewrites a game file in the format given by the extension of `output`.
+pub fn convert_game(input: &str, output: &str) -> Result<(), String> {
+    let format = GameFormat::from_extension(output).ok_or(format!(
+        "Can't tell the format of \"{}\", use one of the extensions: {}.", output,
+        ALL_FORMATS.iter().filter(|format| **format != GameFormat::Script)
+            .map(|format| format.extension()).collect::<Vec<_>>().join(", ")
+    ))?;
+    let (state, _) = read_document(input).map_err(|err| err.to_string())?;
+    let text = write_document(&state, format)?;
+    fs::write(output, text).map_err(|err| format!("Could not write \"{}\": {}", output, err))
+}
+
+/// Upgrades the text of a document to `FORMAT_VERSION`. If no step rewrites documents, only
+/// `format_version` is changed in the text, so comments and layout stay as they are. Otherwise the
+/// upgraded document is written anew, RON ones read and written as a `T`, see `read_value`.
+fn upgrade<T: DeserializeOwned + Serialize>(path: &str, text: &str, format: GameFormat, version: u32)
+    -> Result<String, String> {
+    let steps = migration::pending(version)?;
+    if format == GameFormat::Ron && !migration::rewrites(steps) {
+        return set_format_version(text, format)
+            .filter(|upgraded| read_as::<Versioned>(path, upgraded, format)
+                .is_ok_and(|versioned| versioned.format_version == FORMAT_VERSION))
+            .ok_or(format!("Could not find where to put the format version in \"{}\".", path));
+    }
+    let mut document = read_value::<T>(path, text, format).map_err(|err| err.to_string())?;
+    migration::migrate(&mut document, version)?;
+    if format == GameFormat::Ron {
+        let upgraded: T = serde_json::from_value(document).map_err(|err| err.to_string())?;
+        return write_document(&upgraded, format);
+    }
+    if !migration::rewrites(steps) {
+        let upgraded = set_format_version(text, format)
+            .filter(|upgraded| read_as::<Value>(path, upgraded, format).ok().as_ref() == Some(&document));
+        if let Some(upgraded) = upgraded {
+            return Ok(upgraded);
+        }
+    }
+    write_document(&document, format)
+}
+
+/// Sets `format_version` at the top of a document in its text, leaving everything else as written.
+/// The result may not be what was meant for unusual layouts, so it has to be checked.
+fn set_format_version(text: &str, format: GameFormat) -> Option<String> {
+    let (key, separator, field) = match format {
+        GameFormat::Json => (r#""format_version""#, ":", format!("\"format_version\": {},", FORMAT_VERSION)),
+        GameFormat::Toml => ("format_version", "=", format!("format_version = {}", FORMAT_VERSION)),
+        GameFormat::Yaml => ("format_version", ":", format!("format_version: {}", FORMAT_VERSION)),
+        GameFormat::Ron => ("format_version", ":", format!("format_version: {},", FORMAT_VERSION)),
+        GameFormat::Script => return None,
+    };
+    let existing = Regex::new(&format!(r"(?m)((?:^|[{{(,])[ \t]*{}[ \t]*{}[ \t]*)\d+", key, separator)).unwrap();
+    if existing.is_match(text) {
+        return Some(existing.replace(text, format!("${{1}}{}", FORMAT_VERSION).as_str()).into_owned());
+    }
+    match format {
+        // First in the brace or parenthesis that opens the document, on a line of its own indented like
+        // the next field, unless the document is written on one line.
+        GameFormat::Json | GameFormat::Ron => {
+            let open = text.find(if format == GameFormat::Json { '{' } else { '(' })? + 1;
+            let line_end = text[open..].find('\n').map_or(text.len(), |end| open + end + 1);
+            let rest = text[open..line_end].trim();
+            if !rest.is_empty() && !rest.starts_with("//") {
+                return Some(format!("{}{} {}", &text[..open], field, &text[open..]));
+            }
+            let next = text[line_end..].lines().find(|line| !line.trim().is_empty()).unwrap_or("");
+            let indent = &next[..next.len() - next.trim_start().len()];
+            Some(format!("{}{}{}\n{}", &text[..line_end], indent, field, &text[line_end..]))
+        }
+        // Before the first key, after leading comments and YAML directives.
+        _ => {
+            let mut start = 0;
+            for line in text.split_inclusive('\n') {
+                let trimmed = line.trim();
+                let is_key = !trimmed.is_empty() && !trimmed.starts_with('#') && !trimmed.starts_with("---") &&
+                    !trimmed.starts_with('%');
+                if is_key {
+                    break;
+                }
+                start += line.len();
+            }
+            Some(format!("{}{}\n{}", &text[..start], field, &text[start..]))
+        }
+    }
+}
+
+/// A file `migrate_game` is going to rewrite.
+struct Upgraded {
+    path: PathBuf,
+    backup: String,
+    text: String,
+}
+
+/// Upgrades a game file, and the files it includes, to the current format version in place.
+/// Every file that changes is copied to a `.bak` file next to it first. Nothing is written
+/// until all files are upgraded and none of their backups exists, so a game is never left half upgraded.
+/// Returns what happened to every file.
+pub fn migrate_game(path: &str) -> Result<Vec<String>, String> {
+    let mut upgraded = Vec::new();
+    let mut report = Vec::new();
+    migrate_file(Path::new(path), true, &mut Vec::new(), &mut upgraded, &mut report)?;
+    if let Some(file) = upgraded.iter().find(|file| Path::new(&file.backup).exists()) {
+        return Err(format!("The backup \"{}\" already exists, move it away first. No file was changed.", file.backup));
+    }
+    for file in upgraded {
+        let display = file.path.display();
+        fs::copy(&file.path, &file.backup).map_err(|err| format!("Could not back up \"{}\": {}", display, err))?;
+        fs::write(&file.path, file.text).map_err(|err| format!("Could not write \"{}\": {}", display, err))?;
+    }
+    Ok(report)
+}
+
+fn migrate_file(path: &Path, main: bool, done: &mut Vec<PathBuf>, upgraded: &mut Vec<Upgraded>,
+                report: &mut Vec<String>) -> Result<(), String> {
+    let display = path.display().to_string();
+    let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if done.contains(&key) {
+        return Ok(());
+    }
+    done.push(key);
+    let bytes = read_source(&display).map_err(|err| err.to_string())?;
+    if bytes.starts_with(ZIP_MAGIC) {
+        return Err(format!("\"{}\" is an .agf package, upgrade the files in it before packing them.", display));
+    }
+    let text = String::from_utf8(bytes).map_err(|_| LoadError::UnknownFormat { path: display.clone() }.to_string())?;
+    let format = GameFormat::from_extension(&display).or_else(|| detect_format(&text))
+        .ok_or_else(|| LoadError::UnknownFormat { path: display.clone() }.to_string())?;
+    if format == GameFormat::Script {
+        report.push(format!("\"{}\" is a story script, those are always up to date.", display));
+        return Ok(());
+    }
+    let Versioned { format_version: version, include: includes } =
+        read_as(&display, &text, format).map_err(|err| err.to_string())?;
+    if version == FORMAT_VERSION {
+        report.push(format!("\"{}\" already uses format version {}.", display, FORMAT_VERSION));
+    } else {
+        let upgraded_text = if main {
+            upgrade::<GameState>(&display, &text, format, version)?
         } else {
-            Command::new("sh")
-                .arg("-c")
-                .arg(command)
-                .output()
-                .expect("failed to execute process")
+            upgrade::<GamePart>(&display, &text, format, version)?
         };
+        if main {
+            parse_document(&display, &upgraded_text, format).map(|_| ())
+        } else {
+            deserialize::<GamePart>(&display, &upgraded_text, format).map(|_| ())
+        }.map_err(|err| format!("The upgraded \"{}\" can't be read, no file was changed: {}", display, err))?;
+        let backup = format!("{}.bak", display);
+        report.push(format!("Upgraded \"{}\" from format version {} to {}, the original is in \"{}\":",
+                            display, version, FORMAT_VERSION, backup));
+        let steps = migration::pending(version)?;
+        report.extend(steps.iter().map(|step| format!("  - {}", step.summary)));
+        upgraded.push(Upgraded { path: path.to_path_buf(), backup, text: upgraded_text });
+    }
+    let dir = path.parent().map_or(PathBuf::new(), Path::to_path_buf);
+    for include in includes {
+        migrate_file(&dir.join(include), false, done, upgraded, report)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the files of a game to a directory of its own and returns the path of the first one.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> String {
+        let dir = std::env::temp_dir().join(format!("advgame-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, text) in files {
+            fs::write(dir.join(name), text).unwrap();
+        }
+        dir.join(files[0].0).to_string_lossy().to_string()
+    }
+
+    fn game(include: &[&str]) -> String {
+        serde_json::json!({
+            "format_version": FORMAT_VERSION,
+            "name": "Test",
+            "include": include,
+            "stats": [{"id": "hp", "name": "HP", "default_value": 1}],
+            "stages": [{"index": "start", "name": "Start", "text": ["Hi."],
+                        "options": [{"target_stage": "end", "text": ["Go."]}]}],
+            "item_slots": [],
+            "items": [],
+            "entry_stage": "start",
+            "exit_stage": "end",
+        }).to_string()
+    }
+
+    fn part(include: &[&str], stage: &str) -> String {
+        serde_json::json!({
+            "format_version": FORMAT_VERSION,
+            "include": include,
+            "stages": [{"index": stage, "name": stage, "text": ["The end."], "options": []}],
+        }).to_string()
+    }
+
+    #[test]
+    fn detects_formats_by_content() {
+        assert_eq!(detect_format("\u{feff}{\"name\": \"Test\"}"), Some(GameFormat::Json));
+        assert_eq!(detect_format("# comment\nname = \"Test\""), Some(GameFormat::Toml));
+        assert_eq!(detect_format("[[stats]]\nid = 0"), Some(GameFormat::Toml));
+        assert_eq!(detect_format("---\nname: Test"), Some(GameFormat::Yaml));
+        assert_eq!(detect_format("// comment\n(name: \"Test\")"), Some(GameFormat::Ron));
+        assert_eq!(detect_format("title: Test\n=== Start\nHi.\n"), Some(GameFormat::Script));
+        assert_eq!(detect_format("Just some text."), None);
     }
-    let mut buf = String::new();
-    File::open(path)
-        .and_then(|mut file| file.read_to_string(&mut buf))
-        .and_then(|size| Ok(buf[..size].to_string()))
-        .and_then(|format_str| Ok(print!("{}", format_str)))
-        .expect("There was an error. Sorry!")
-}
\ No newline at end of file
+
+    #[test]
+    fn detects_data_formats_with_lines_like_stage_headers() {
+        assert_eq!(detect_format("name = \"Test\"\nlicense = \"\"\"\n=== not a stage\n\"\"\"\n"),
+                   Some(GameFormat::Toml));
+        assert_eq!(detect_format("name: Test\nlicense: >\n  text\n===\n"), Some(GameFormat::Script));
+    }
+
+    #[test]
+    fn sets_the_format_version_in_the_text() {
+        let version = |field: &str| format!("{}{}", field, FORMAT_VERSION);
+        assert_eq!(set_format_version("{\n  // The game.\n  \"name\": \"Test\"\n}", GameFormat::Json).unwrap(),
+                   format!("{{\n  {},\n  // The game.\n  \"name\": \"Test\"\n}}", version("\"format_version\": ")));
+        assert_eq!(set_format_version("{\"format_version\": 1, \"name\": \"Test\"}", GameFormat::Json).unwrap(),
+                   format!("{{{}, \"name\": \"Test\"}}", version("\"format_version\": ")));
+        assert_eq!(set_format_version("# The game.\nname = \"Test\"\n", GameFormat::Toml).unwrap(),
+                   format!("# The game.\n{}\nname = \"Test\"\n", version("format_version = ")));
+        assert_eq!(set_format_version("---\nname: Test\n", GameFormat::Yaml).unwrap(),
+                   format!("---\n{}\nname: Test\n", version("format_version: ")));
+        assert_eq!(set_format_version("(\n    name: \"Test\",\n)", GameFormat::Ron).unwrap(),
+                   format!("(\n    {},\n    name: \"Test\",\n)", version("format_version: ")));
+    }
+
+    #[test]
+    fn reads_older_documents_as_they_were_meant() {
+        let text = game(&[]).replace(&format!("\"format_version\":{}", FORMAT_VERSION), "\"format_version\":1")
+            .replace("Hi.", "5 * 3 is $$15");
+        let state = parse_document("old.json", &text, GameFormat::Json).unwrap();
+        assert_eq!(state.stages[0].text.to_vec(), vec!["5 ** 3 is $$$$15".to_string()]);
+    }
+
+    #[test]
+    fn migrates_no_file_if_a_backup_is_in_the_way() {
+        let old = |text: String| text.replace(&format!("\"format_version\":{},", FORMAT_VERSION), "");
+        let path = write_files("migrate", &[
+            ("main.json", &old(game(&["end.json"]))),
+            ("end.json", &old(part(&[], "end"))),
+            ("end.json.bak", "taken"),
+        ]);
+        let main = fs::read_to_string(&path).unwrap();
+        assert!(migrate_game(&path).unwrap_err().contains("end.json.bak\" already exists"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), main);
+        assert!(!Path::new(&format!("{}.bak", path)).exists());
+        fs::remove_file(Path::new(&path).with_file_name("end.json.bak")).unwrap();
+        assert_eq!(migrate_game(&path).unwrap().len(), 2 * (1 + FORMAT_VERSION as usize));
+        assert_eq!(fs::read_to_string(format!("{}.bak", path)).unwrap(), main);
+        assert!(parse_game(&path).is_ok());
+    }
+
+    #[test]
+    fn merges_included_files_once() {
+        let path = write_files("includes", &[
+            ("main.json", &game(&["a.json", "b.json"])),
+            ("a.json", &part(&["end.json"], "a")),
+            ("b.json", &part(&["end.json"], "b")),
+            ("end.json", &part(&[], "end")),
+        ]);
+        let (state, sources, collisions) = read_game(&path).unwrap();
+        let names: Vec<&str> = state.stages.iter().map(|stage| &stage.name[..]).collect();
+        assert_eq!(names, vec!["Start", "a", "end", "b"]);
+        assert_eq!(sources.len(), 4);
+        assert!(collisions.is_empty());
+    }
+
+    #[test]
+    fn reports_ids_included_files_define_again_once() {
+        let path = write_files("collision", &[
+            ("main.json", &game(&["a.json"])),
+            ("a.json", &serde_json::json!({
+                "format_version": FORMAT_VERSION,
+                "stats": [{"id": "hp", "name": "Mana", "default_value": 0}],
+                "stages": [{"index": "end", "name": "End", "text": ["The end."], "options": []}],
+            }).to_string()),
+        ]);
+        match parse_game(&path) {
+            Err(LoadError::Invalid { diagnostics, .. }) => {
+                let messages: Vec<&str> = diagnostics.iter()
+                    .filter(|diagnostic| diagnostic.severity == diagnostics::Severity::Error)
+                    .map(|diagnostic| &diagnostic.message[..])
+                    .collect();
+                assert_eq!(messages.len(), 1, "{:?}", messages);
+                assert!(messages[0].starts_with("The id \"hp\" is already used in "), "{}", messages[0]);
+                assert!(diagnostics[0].file.as_ref().is_some_and(|file| file.ends_with("a.json")));
+            }
+            other => panic!("expected the collision to be reported, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn reports_include_cycles() {
+        let path = write_files("cycle", &[
+            ("main.json", &game(&["a.json"])),
+            ("a.json", &part(&["b.json"], "a")),
+            ("b.json", &part(&["a.json"], "end")),
+        ]);
+        match read_game(&path) {
+            Err(LoadError::Include { message, .. }) => {
+                assert!(message.starts_with("include cycle "), "{}", message);
+                let files: Vec<&str> = message.split(" -> ")
+                    .map(|file| Path::new(file).file_name().unwrap().to_str().unwrap())
+                    .collect();
+                assert_eq!(files, vec!["a.json", "b.json", "a.json"]);
+            }
+            other => panic!("expected an include cycle, got {:?}", other.map(|_| ())),
+        }
+    }
+}
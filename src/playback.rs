@@ -1,45 +1,330 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
 use crate::console::*;
 use crate::game_state::GameState;
+use crate::history::History;
+use crate::locale;
+use crate::markup;
+use crate::profile::Profile;
+use crate::replay;
+use crate::replay::Recording;
+use crate::save;
+use crate::save::{SaveFile, Snapshot};
+use crate::transcript;
+use crate::transcript::Transcript;
+
+fn announce_achievements(cls: &Console, state: &mut GameState, profile: &mut Profile) {
+    let new_achievements = state.take_new_achievements();
+    if new_achievements.is_empty() {
+        return;
+    }
+    for id in new_achievements {
+        let achievement = &state.achievements[id];
+        if !profile.unlock(achievement.name.original()) {
+            continue;
+        }
+        // Popups can't show styles.
+        let mut lines = vec![markup::strip(&achievement.name)];
+        if !achievement.description.is_empty() {
+            lines.push(markup::strip(&achievement.description));
+        }
+        cls.show_popup("Achievement unlocked!", &lines);
+    }
+    // Replays only show what happened, they don't unlock anything for real.
+    if cls.is_replaying() {
+        return;
+    }
+    if let Err(err) = profile.save() {
+        dprintln!("Could not save the profile: {}", err);
+    }
+}
+
+/// Adds the whole seconds elapsed since `clock` to the playtime.
+fn tick(state: &mut GameState, clock: &mut Instant) {
+    let secs = clock.elapsed().as_secs();
+    state.playtime += secs;
+    *clock += Duration::from_secs(secs);
+}
+
+fn save_game(cls: &Console, state: &GameState) {
+    let manual_saves: Vec<SaveFile> = save::list_saves(state.get_name()).into_iter()
+        .map(|(_, save)| save)
+        .filter(|save| !save.autosave)
+        .collect();
+    let mut entries = vec!["New save slot".to_string()];
+    entries.extend(manual_saves.iter().map(save::describe));
+    let slot_name = match cls.pick("Save game", &entries) {
+        None => return,
+        Some(0) => {
+            let default_name = format!("Save {}", manual_saves.len() + 1);
+            cls.read_line(&format!("Name your save [{}]:", default_name), 40).unwrap_or(default_name)
+        }
+        Some(i) => manual_saves[i - 1].slot_name.clone(),
+    };
+    // Replays go through the picker like the player did, but leave the player's saves alone.
+    let result = if cls.is_replaying() { Ok(()) } else {
+        save::slot_path(state.get_name(), &slot_name, false)
+            .and_then(|path| save::save_game(state, &path, &slot_name, false))
+    };
+    cls.clear();
+    match result {
+        Ok(()) => cls.show_popup("Game saved", &[slot_name]),
+        Err(err) => cls.show_popup("Could not save the game", &[err]),
+    }
+}
+
+/// Lets the player continue from one of the saves. Returns false if the player backed out.
+fn pick_save(cls: &Console, state: &mut GameState) -> bool {
+    let saves = save::list_saves(state.get_name());
+    if saves.is_empty() {
+        return true;
+    }
+    let mut entries = vec!["New game".to_string()];
+    entries.extend(saves.iter().map(|(_, save)| save::describe(save)));
+    loop {
+        match cls.pick("Continue", &entries) {
+            None => return false,
+            Some(0) => return true,
+            Some(i) => match save::load_game(state, &saves[i - 1].0) {
+                Ok(()) => return true,
+                Err(err) => {
+                    cls.clear();
+                    cls.show_popup("Could not load the save", &[err]);
+                }
+            },
+        }
+    }
+}
+
+/// How many choices can be undone.
+const HISTORY_LIMIT: usize = 100;
+
+/// Runs `write` on the transcript if there is one. Errors stop the transcript.
+fn log<F>(cls: &Console, transcript: &mut Option<Transcript>, write: F)
+    where F: FnOnce(&mut Transcript) -> Result<(), String> {
+    let result = match transcript.as_mut() {
+        Some(transcript) => write(transcript),
+        None => return,
+    };
+    if let Err(err) = result {
+        *transcript = None;
+        cls.show_popup("Transcript stopped", &[err]);
+    }
+}
+
+fn toggle_transcript(cls: &Console, state: &GameState, transcript: &mut Option<Transcript>, path: &Path) {
+    cls.clear();
+    // Replays only show the popup, they don't start or stop transcripts.
+    if cls.is_replaying() {
+        let (title, shown) = match transcript {
+            Some(old) => ("Transcript stopped", old.path.display()),
+            None => ("Transcript started", path.display()),
+        };
+        cls.show_popup(title, &[shown.to_string()]);
+        return;
+    }
+    match transcript.take() {
+        Some(old) => cls.show_popup("Transcript stopped", &[old.path.display().to_string()]),
+        None => match Transcript::open(path, state, true) {
+            Ok(new) => {
+                cls.show_popup("Transcript started", &[new.path.display().to_string()]);
+                *transcript = Some(new);
+            }
+            Err(err) => cls.show_popup("Could not start the transcript", &[err]),
+        }
+    }
+}
 
-pub fn main_loop(cls: &Console, mut state: GameState) {
+pub fn main_loop(cls: &Console, mut state: GameState, profile: &mut Profile, config: &Config,
+                 transcript_path: Option<PathBuf>) -> GameState {
     dprintln!("Starting main loop!");
     let mut action = Action::Unimplemented;
+    let mut clock = Instant::now();
+    let mut history = History::new(if state.allow_undo { HISTORY_LIMIT } else { 0 });
+    let mut transcript = None;
+    let start_transcript = transcript_path.is_some();
+    let transcript_path = transcript_path.unwrap_or_else(|| transcript::default_transcript_path(state.get_name()));
+    if start_transcript {
+        match Transcript::open(&transcript_path, &state, false) {
+            Ok(new) => transcript = Some(new),
+            Err(err) => cls.show_popup("Could not start the transcript", &[err]),
+        }
+    }
+    // Whether the current stage still has to be written to the transcript.
+    let mut stage_entered = true;
+    state.check_achievements();
     while match action { Action::Quit => false, _ => true } {
         cls.clear();
+        if stage_entered {
+            log(cls, &mut transcript, |t| t.write_stage(state.get_current_stage(), &state));
+            stage_entered = false;
+        }
         cls.print_stage(state.get_current_stage(), &state);
+        announce_achievements(cls, &mut state, profile);
         action = cls.get_action();
+        tick(&mut state, &mut clock);
+        match action {
+            Action::Save => {
+                save_game(cls, &state);
+                continue;
+            }
+            Action::Resize => continue,
+            Action::PageUp | Action::PageDown => {
+                cls.scroll_pages(if let Action::PageUp = action { -1 } else { 1 });
+                continue;
+            }
+            Action::Transcript => {
+                toggle_transcript(cls, &state, &mut transcript, &transcript_path);
+                stage_entered = true;
+                continue;
+            }
+            Action::Cancel => {
+                if let Some(snapshot) = history.pop() {
+                    match state.restore(snapshot) {
+                        Ok(()) => {
+                            log(cls, &mut transcript, |t| t.write_note("Went back to the previous choice"));
+                            stage_entered = true;
+                        }
+                        Err(err) => dprintln!("Could not undo: {}", err),
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+        let previous_stage = state.current_stage;
+        let snapshot = state.snapshot();
+        if let Some(option) = state.chosen_option(&action) {
+            log(cls, &mut transcript, |t| t.write_choice(option, &state));
+            stage_entered = true;
+        }
         state = state.handle_action(&action);
+        log(cls, &mut transcript, |t| t.write_stat_changes(&snapshot.stats, &state));
+        if state.current_stage != previous_stage {
+            history.push(snapshot);
+            // Replays leave the player's autosaves alone.
+            let autosave = if cls.is_replaying() { Ok(()) } else { save::autosave(&state, config.autosave_slots) };
+            if let Err(err) = autosave {
+                dprintln!("Autosave failed: {}", err);
+            }
+        }
         if state.is_finished() {
             break;
         }
     }
+    announce_achievements(cls, &mut state, profile);
     dprintln!("Exiting main loop!");
+    cls.show_until_key(|| {
+        cls.print_center("Thank you for playing!");
+        cls.print_center_offset("Press any key to exit the application.", 1);
+    });
+    state
+}
+
+fn print_title(cls: &Console, state: &GameState) {
     cls.clear();
-    cls.print_center("Thank you for playing!");
-    cls.print_center_offset("Press any key to exit the application.", 1);
-    cls.get_ch();
+    if state.achievements.is_empty() {
+        cls.print_title_screen(state, &["Press y to play, n to quit."]);
+    } else {
+        cls.print_title_screen(state, &["Press y to play, n to quit.", "", "Press a to view achievements."]);
+    }
+}
+
+pub struct PlayOptions {
+    /// Whether to offer continuing from a save. Off when a save was given on the command line.
+    pub pick_save: bool,
+    /// Where to write the recording of this session.
+    pub record: Option<PathBuf>,
+    /// A recording to play instead of reading the keyboard.
+    pub replay: Option<Recording>,
+    /// Where to write the transcript, if it should be written from the start.
+    pub transcript: Option<PathBuf>,
+    /// The language to play in. Players pick one of the game's languages if it's not given.
+    pub language: Option<String>,
+}
+
+fn write_recording(cls: &Console, state: &GameState, start: Option<Snapshot>, path: &Path) {
+    let recording = Recording {
+        game_hash: save::format_hash(state.source_hash),
+        seed: None,
+        start,
+        actions: cls.take_recording().unwrap_or_default(),
+    };
+    if let Err(err) = replay::write_recording(&recording, path) {
+        cls.clear();
+        cls.show_popup("Could not save the recording", &[err]);
+    }
 }
 
-pub fn play_game(state: GameState) {
+/// Shows the title screen and plays the game. Returns the final state if the game was played.
+pub fn play_game(mut state: GameState, options: PlayOptions) -> Option<GameState> {
     let cls = Console::new();
+    cls.set_undo(state.allow_undo);
     dprintln!("Welcome to the advgame debug mode!");
-    cls.print_center(&format!("You have successfully loaded \"{}\"!", state.get_name()));
-    cls.print_center_offset("Play now? [y]/n", 1);
+    let config = Config::load();
+    let mut profile = Profile::load(state.get_name());
+    for id in 0..state.achievements.len() {
+        if profile.is_unlocked(state.achievements[id].name.original()) {
+            state.mark_achievement_unlocked(id);
+        }
+    }
+    if let Some(recording) = options.replay {
+        cls.start_replay(recording.actions);
+    }
+    // A save given on the command line is where the recording starts, so it has to be kept with it.
+    let start = if options.pick_save { None } else { Some(state.snapshot()) };
+    if options.record.is_some() {
+        cls.start_recording();
+    }
+    // Saves differ between computers, so recorded sessions always start a new game.
+    let pick_save = options.pick_save && options.record.is_none() && !cls.is_replaying();
+    match options.language {
+        Some(language) => state.set_language(&language),
+        None if !state.languages().is_empty() => {
+            let names: Vec<String> = state.languages().iter().map(|locale| locale::language_name(locale)).collect();
+            if let Some(choice) = cls.pick("Choose a language", &names) {
+                let language = state.languages()[choice].clone();
+                state.set_language(&language);
+            }
+        }
+        None => {}
+    }
+    print_title(&cls, &state);
 
     let mut response_guard: Option<bool> = None;
     while response_guard.is_none() {
         match cls.get_action() {
-            Action::Confirm => response_guard = Some(true),
-            Action::Cancel => response_guard = Some(false),
+            Action::Confirm if !pick_save || self::pick_save(&cls, &mut state) => response_guard = Some(true),
+            Action::Confirm => print_title(&cls, &state),
+            Action::Cancel | Action::Quit => response_guard = Some(false),
+            Action::Achievements => {
+                cls.show_until_key(|| cls.print_achievements(&state, &profile));
+                print_title(&cls, &state);
+            }
+            Action::Resize => print_title(&cls, &state),
             _ => {}
         }
     }
-    if let Some(true) = response_guard {
-        cls.print_center_offset("Press any key to start game!", 2);
-        cls.get_ch();
-        main_loop(&cls, state);
+    let final_state = if let Some(true) = response_guard {
+        cls.show_until_key(|| {
+            if state.metadata.intro.is_empty() {
+                cls.print_title_screen(&state, &["Press any key to start game!"]);
+            } else {
+                cls.print_intro(&state);
+                cls.print_center_offset("Press any key to start game!", state.metadata.intro.len() as i32 / 2 + 1);
+            }
+        });
+        Some(main_loop(&cls, state, &mut profile, &config, options.transcript))
     } else {
-        cls.print_center_offset("Too bad!", 2);
-        cls.get_ch();
+        cls.show_until_key(|| {
+            cls.print_center("Too bad!");
+        });
+        None
+    };
+    if let (Some(path), Some(state)) = (options.record, final_state.as_ref()) {
+        write_recording(&cls, state, start, &path);
     }
+    final_state
 }
@@ -0,0 +1,84 @@
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use crate::markup;
+
+/// Returns the directory where advgame keeps its per-user files.
+pub fn data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("advgame"))
+}
+
+/// Turns a game name into something that is safe to use as a file name.
+pub fn file_stem(game_name: &str) -> String {
+    let stem: String = game_name.chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    if stem.is_empty() { "unnamed".to_string() } else { stem }
+}
+
+/// Where the files of a game go. Game names can have markup, see `markup`, which doesn't change it.
+pub fn game_stem(game_name: &str) -> String {
+    file_stem(&markup::strip(game_name))
+}
+
+/// Things that persist between playthroughs of a single game.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Profile {
+    pub unlocked_achievements: Vec<String>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Profile {
+    fn path_for(game_name: &str) -> Option<PathBuf> {
+        data_dir().map(|dir| dir.join("profiles").join(game_stem(game_name) + ".json"))
+    }
+
+    /// Loads the profile of the given game. A missing or broken profile file
+    /// results in an empty profile, so the game can always be played.
+    pub fn load(game_name: &str) -> Profile {
+        let path = Profile::path_for(game_name);
+        let mut profile = path.as_ref()
+            .and_then(|path| File::open(path).ok())
+            .and_then(|mut file| {
+                let mut buf = String::new();
+                file.read_to_string(&mut buf).ok().map(|_| buf)
+            })
+            .and_then(|buf| match serde_json::from_str::<Profile>(&buf) {
+                Ok(profile) => Some(profile),
+                Err(err) => {
+                    dprintln!("Ignoring a broken profile file: {}", err);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        profile.path = path;
+        profile
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("Could not find the user data directory!".to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        fs::write(path, json).map_err(|err| err.to_string())
+    }
+
+    /// Achievements are kept by their names without markup, so styling a name keeps it unlocked.
+    pub fn is_unlocked(&self, achievement_name: &str) -> bool {
+        self.unlocked_achievements.contains(&markup::strip(achievement_name))
+    }
+
+    /// Returns true if the achievement wasn't unlocked before.
+    pub fn unlock(&mut self, achievement_name: &str) -> bool {
+        if self.is_unlocked(achievement_name) {
+            false
+        } else {
+            self.unlocked_achievements.push(markup::strip(achievement_name));
+            true
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use std::fs;
+use std::path::Path;
+
+use crate::console::Action;
+use crate::save::Snapshot;
+
+/// A recorded input session, replayable with `--replay`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Recording {
+    /// Hash of the game file the session was recorded with, see `misc::fnv1a_hash`.
+    pub game_hash: String,
+    /// Seed of the random number generator. Games have no randomness yet, so it's always empty.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// The state the session started from, if it was recorded after loading a save with `--load`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<Snapshot>,
+    pub actions: Vec<Action>,
+}
+
+pub fn write_recording(recording: &Recording, path: &Path) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(recording).map_err(|err| err.to_string())?;
+    fs::write(path, json).map_err(|err| format!("Could not write \"{}\": {}", path.display(), err))
+}
+
+pub fn read_recording(path: &Path) -> Result<Recording, String> {
+    let json = fs::read_to_string(path)
+        .map_err(|err| format!("Could not read \"{}\": {}", path.display(), err))?;
+    serde_json::from_str::<Recording>(&json)
+        .map_err(|err| format!("\"{}\" is not a valid recording, line {}:{}\n{}",
+                               path.display(), err.line(), err.column(), err))
+}
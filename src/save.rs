@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::game_components::StatValue;
+use crate::game_state::GameState;
+use crate::markup;
+use crate::profile;
+
+/// The runtime part of a `GameState`, i.e. everything that changes while playing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    pub stats: Vec<StatValue>,
+    pub current_stage: usize,
+    pub current_option: usize,
+    pub item_slot_filling: HashMap<String, usize>,
+    /// The items the player holds, see `GameState::inventory`.
+    /// Saves from before it was kept hold only the equipped items.
+    #[serde(default)]
+    pub held_items: Vec<usize>,
+    pub finished: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SaveFile {
+    /// Hash of the game file the save was made with, see `misc::fnv1a_hash`.
+    pub game_hash: String,
+    #[serde(default)]
+    pub slot_name: String,
+    #[serde(default)]
+    pub autosave: bool,
+    /// Seconds since the Unix epoch.
+    #[serde(default)]
+    pub timestamp: u64,
+    /// One more than the newest save of the game had, to order saves made within the same second.
+    #[serde(default)]
+    pub sequence: u64,
+    #[serde(default)]
+    pub stage_name: String,
+    /// Seconds spent playing.
+    #[serde(default)]
+    pub playtime: u64,
+    pub state: Snapshot,
+}
+
+const AUTOSAVE_PREFIX: &str = "Autosave ";
+
+pub fn format_hash(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0)
+}
+
+pub fn saves_dir(game_name: &str) -> Option<PathBuf> {
+    profile::data_dir().map(|dir| dir.join("saves").join(profile::game_stem(game_name)))
+}
+
+/// Autosaves and manual saves get different file name prefixes, so that they never overwrite each other.
+/// A slot keeps the file it was saved to before. Different slot names can make the same file name,
+/// like "Save 1" and "save_1", so new slots get a counter if their file is taken.
+pub fn slot_path(game_name: &str, slot_name: &str, autosave: bool) -> Result<PathBuf, String> {
+    let dir = saves_dir(game_name).ok_or("Could not find the user data directory!".to_string())?;
+    let saved = list_saves(game_name).into_iter()
+        .find(|(_, save)| save.slot_name == slot_name && save.autosave == autosave);
+    if let Some((path, _)) = saved {
+        return Ok(path);
+    }
+    let stem = format!("{}{}", if autosave { "auto_" } else { "save_" }, profile::file_stem(slot_name));
+    let path = (1..)
+        .map(|i| dir.join(if i == 1 { format!("{}.json", stem) } else { format!("{}_{}.json", stem, i) }))
+        .find(|path| !path.exists())
+        .unwrap();
+    Ok(path)
+}
+
+pub fn save_game(state: &GameState, path: &Path, slot_name: &str, autosave: bool) -> Result<(), String> {
+    let save = SaveFile {
+        game_hash: format_hash(state.source_hash),
+        slot_name: slot_name.to_string(),
+        autosave,
+        timestamp: now(),
+        sequence: list_saves(state.get_name()).iter().map(|(_, save)| save.sequence + 1).max().unwrap_or(1),
+        stage_name: markup::strip(&state.get_current_stage().name),
+        playtime: state.playtime,
+        state: state.snapshot(),
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(&save).map_err(|err| err.to_string())?;
+    fs::write(path, json).map_err(|err| format!("Could not write \"{}\": {}", path.display(), err))
+}
+
+pub fn read_save(path: &Path) -> Result<SaveFile, String> {
+    let json = fs::read_to_string(path)
+        .map_err(|err| format!("Could not read \"{}\": {}", path.display(), err))?;
+    serde_json::from_str::<SaveFile>(&json)
+        .map_err(|err| format!("\"{}\" is not a valid save file, line {}:{}\n{}",
+                               path.display(), err.line(), err.column(), err))
+}
+
+/// Restores a save on top of a freshly parsed game.
+pub fn load_game(state: &mut GameState, path: &Path) -> Result<(), String> {
+    let save = read_save(path)?;
+    if save.game_hash != format_hash(state.source_hash) {
+        return Err(format!("\"{}\" was saved with a different version of \"{}\" and can't be loaded.",
+                           path.display(), state.get_name()));
+    }
+    state.restore(save.state)?;
+    state.playtime = save.playtime;
+    Ok(())
+}
+
+/// Returns all readable saves of the game, newest first.
+pub fn list_saves(game_name: &str) -> Vec<(PathBuf, SaveFile)> {
+    let entries = match saves_dir(game_name).and_then(|dir| fs::read_dir(dir).ok()) {
+        Some(entries) => entries,
+        None => return Vec::new(),
+    };
+    let mut saves: Vec<(PathBuf, SaveFile)> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| match read_save(&path) {
+            Ok(save) => Some((path, save)),
+            Err(err) => {
+                dprintln!("Skipping a broken save: {}", err);
+                None
+            }
+        })
+        .collect();
+    saves.sort_by_key(|(_, save)| std::cmp::Reverse((save.timestamp, save.sequence)));
+    saves
+}
+
+/// Writes the state to the next autosave slot, overwriting the oldest one
+/// once all `slot_count` slots are used.
+pub fn autosave(state: &GameState, slot_count: usize) -> Result<(), String> {
+    if slot_count == 0 {
+        return Ok(());
+    }
+    let autosaves: Vec<(PathBuf, SaveFile)> = list_saves(state.get_name()).into_iter()
+        .filter(|(_, save)| save.autosave)
+        .collect();
+    let slot_name = (1..=slot_count)
+        .map(|i| format!("{}{}", AUTOSAVE_PREFIX, i))
+        .find(|name| autosaves.iter().all(|(_, save)| &save.slot_name != name))
+        .or_else(|| autosaves.iter()
+            .min_by_key(|(_, save)| (save.sequence, save.timestamp))
+            .map(|(_, save)| save.slot_name.clone()))
+        .unwrap_or(format!("{}1", AUTOSAVE_PREFIX));
+    save_game(state, &slot_path(state.get_name(), &slot_name, true)?, &slot_name, true)
+}
+
+/// A one line summary used by the slot pickers.
+pub fn describe(save: &SaveFile) -> String {
+    format!("{} | {} | played {} | {}", save.slot_name, save.stage_name,
+            format_playtime(save.playtime), format_timestamp(save.timestamp))
+}
+
+pub fn format_playtime(secs: u64) -> String {
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Formats seconds since the Unix epoch as a UTC date and time.
+pub fn format_timestamp(secs: u64) -> String {
+    // Converts days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (secs / 86400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, secs / 3600 % 24, secs / 60 % 60)
+}
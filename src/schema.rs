@@ -0,0 +1,185 @@
+//! The game file format, described by the Rust types of `GameState` itself:
+//! as a JSON Schema for editors, and as a short reference for people.
+
+use schemars::schema::{InstanceType, RootSchema, Schema, SchemaObject, SingleOrVec};
+use schemars::schema_for;
+
+use crate::game_state::GameState;
+use crate::include::GamePart;
+
+/// The schema of game files, or of files included by games if `included` is set.
+pub fn game_schema(included: bool) -> RootSchema {
+    if included { schema_for!(GamePart) } else { schema_for!(GameState) }
+}
+
+pub fn print_schema(included: bool) {
+    match serde_json::to_string_pretty(&game_schema(included)) {
+        Ok(json) => println!("{}", json),
+        Err(err) => eprintln!("Could not write the schema: {}", err),
+    }
+}
+
+const REFERENCE_INTRO: &str = "\
+- Struct means that all fields must be included.
+- Enum means that you choose one of the options.
+- usize means that it must be a positive number.
+- u32 means that it must be a positive number below 2^32.
+- i32 means that it must be a number between -2^31 and 2^31.
+- Vec<X> means you need to put X objects in a [X, X, X] list.
+- '=' sign after a field means it is optional, and shows what it defaults to.
+- Lines starting with /// describe the thing below them.
+";
+
+fn object(schema: &Schema) -> Option<&SchemaObject> {
+    match schema {
+        Schema::Object(object) => Some(object),
+        Schema::Bool(_) => None,
+    }
+}
+
+fn type_name(schema: &SchemaObject) -> String {
+    // A reference with a description or a default is wrapped in `allOf`.
+    let wrapped = schema.subschemas.as_ref().and_then(|subschemas| subschemas.all_of.as_ref());
+    if let Some([inner]) = wrapped.map(|all_of| &all_of[..]) {
+        return object(inner).map_or("?".to_string(), type_name);
+    }
+    // Optional references are either the reference or null.
+    let any_of = schema.subschemas.as_ref().and_then(|subschemas| subschemas.any_of.as_ref());
+    if let Some(options) = any_of {
+        let is_null = |option: &&Schema| object(option).is_some_and(|option| {
+            option.instance_type == Some(SingleOrVec::Single(Box::new(InstanceType::Null)))
+        });
+        if let [value] = &options.iter().filter(|option| !is_null(option)).collect::<Vec<_>>()[..] {
+            if options.len() == 2 {
+                return object(value).map_or("?".to_string(), type_name);
+            }
+        }
+    }
+    if let Some(reference) = &schema.reference {
+        return reference.rsplit('/').next().unwrap_or(reference).to_string();
+    }
+    // Optional values are "null" or something else, the reference shows them with their default instead.
+    let instance_type = match &schema.instance_type {
+        Some(SingleOrVec::Single(instance_type)) => Some(**instance_type),
+        Some(SingleOrVec::Vec(types)) => types.iter().copied().find(|type_| *type_ != InstanceType::Null),
+        None => None,
+    };
+    let instance_type = match instance_type {
+        Some(instance_type) => instance_type,
+        None => return "?".to_string(),
+    };
+    match instance_type {
+        InstanceType::Array => {
+            let items = schema.array.as_ref().and_then(|array| match &array.items {
+                Some(SingleOrVec::Single(items)) => object(items).map(type_name),
+                _ => None,
+            });
+            format!("Vec<{}>", items.unwrap_or("?".to_string()))
+        }
+        InstanceType::Integer => match schema.format.as_ref().map(|format| &format[..]) {
+            Some("uint") => "usize".to_string(),
+            Some("uint32") => "u32".to_string(),
+            Some("int32") => "i32".to_string(),
+            Some(format) => format.to_string(),
+            None => "integer".to_string(),
+        },
+        InstanceType::String => "String".to_string(),
+        InstanceType::Object => match schema.object.as_ref().and_then(|object| object.additional_properties.as_ref()) {
+            Some(values) => format!("Map<String, {}>", object(values).map_or("?".to_string(), type_name)),
+            None => "object".to_string(),
+        },
+        InstanceType::Boolean => "bool".to_string(),
+        other => format!("{:?}", other).to_lowercase(),
+    }
+}
+
+fn doc_lines(schema: &SchemaObject, indent: &str) -> String {
+    let description = schema.metadata.as_ref().and_then(|metadata| metadata.description.as_ref());
+    description.map_or(String::new(), |description| {
+        description.lines().map(|line| format!("{}/// {}\n", indent, line).replace("/// \n", "///\n")).collect()
+    })
+}
+
+/// `field: Type`, or `field: Type = default` for optional fields.
+fn field((name, schema, required): &(String, &SchemaObject, bool)) -> String {
+    let default = schema.metadata.as_ref().and_then(|metadata| metadata.default.as_ref());
+    match default {
+        Some(default) => format!("{}: {} = {}", name, type_name(schema), default),
+        None if *required => format!("{}: {}", name, type_name(schema)),
+        None if schema.array.is_some() => format!("{}: {} = []", name, type_name(schema)),
+        // Like `Localized_Array_of_String`, which can be written as a list.
+        None if type_name(schema).contains("Array") => format!("{}: {} = []", name, type_name(schema)),
+        None if schema.reference.is_some() => format!("{}: {} = {{}}", name, type_name(schema)),
+        None => format!("{}: {} = null", name, type_name(schema)),
+    }
+}
+
+fn fields(schema: &SchemaObject) -> Vec<(String, &SchemaObject, bool)> {
+    schema.object.as_ref().map_or(Vec::new(), |validation| {
+        validation.properties.iter()
+            .filter_map(|(name, property)| object(property)
+                .map(|property| (name.clone(), property, validation.required.contains(name))))
+            .collect()
+    })
+}
+
+/// Renders the options of an enum that one subschema allows, like `if_stat_higher { stat_id: Id, higher_than: i32 }`.
+fn variants(schema: &SchemaObject) -> Vec<(String, &SchemaObject)> {
+    if let Some(values) = &schema.enum_values {
+        return values.iter().map(|value| (value.as_str().unwrap_or("?").to_string(), schema)).collect();
+    }
+    fields(schema).into_iter().map(|(name, content, _)| {
+        let inner = fields(content);
+        let variant = if content.reference.is_some() || inner.is_empty() {
+            format!("{}({})", name, type_name(content))
+        } else {
+            let inner: Vec<String> = inner.iter().map(field).collect();
+            format!("{} {{ {} }}", name, inner.join(", "))
+        };
+        (variant, schema)
+    }).collect()
+}
+
+fn definition(name: &str, schema: &SchemaObject) -> String {
+    let mut text = doc_lines(schema, "");
+    let options = schema.subschemas.as_ref()
+        .and_then(|subschemas| subschemas.one_of.as_ref().or(subschemas.any_of.as_ref()));
+    if let Some(options) = options {
+        let options: Vec<&SchemaObject> = options.iter().filter_map(object).collect();
+        if options.iter().all(|option| option.enum_values.is_some() || option.object.is_some()) {
+            text += &format!("enum {} {{\n", name);
+            for option in options {
+                for (variant, variant_schema) in variants(option) {
+                    text += &doc_lines(variant_schema, "    ");
+                    text += &format!("    {},\n", variant);
+                }
+            }
+        } else {
+            let types: Vec<String> = options.into_iter().map(type_name).collect();
+            return text + &format!("type {} = {};\n", name, types.join(" | "));
+        }
+    } else {
+        text += &format!("struct {} {{\n", name);
+        for field_info in fields(schema) {
+            text += &doc_lines(field_info.1, "    ");
+            text += &format!("    {},\n", field(&field_info));
+        }
+    }
+    text + "}\n"
+}
+
+/// A human readable description of every type in game files, starting with the whole game.
+pub fn format_reference() -> String {
+    let root = game_schema(false);
+    let mut text = REFERENCE_INTRO.to_string();
+    text += "\n";
+    text += &definition("GameState", &root.schema);
+    for (name, schema) in root.definitions.iter() {
+        if let Some(schema) = object(schema) {
+            text += "\n";
+            text += &definition(name, schema);
+        }
+    }
+    text
+}
+
@@ -0,0 +1,677 @@
+//! The story script language, a plain text way of writing games.
+//!
+//! ```text
+//! // Comments start with two slashes.
+//! title: Test game
+//! intro: A game to test the engine with.
+//! stat Attack = 2
+//! slot weapon
+//! item Blade of Truth
+//!     equip weapon
+//!     when_equipped {Attack += 2137}
+//!     when_unequipped {Attack -= 2137}
+//! achievement Mighty
+//!     description: Raise your attack above 100.
+//!     live {if Attack > 100}
+//!
+//! === Stage 1
+//! Your attack is ${stat.Attack}.
+//! * Take the sword. -> The Cave of Wonders {use Blade of Truth}
+//! * This only shows up when your attack is 1. {if Attack == 1}
+//!   Lines starting with spaces continue the option. -> Stage 1
+//! ```
+//!
+//! The first stage is the entry stage, and the only stage without options is the exit stage,
+//! unless `entry:` or `exit:` say otherwise. Everything is referenced by name.
+//! Options start with an asterisk and a space, so stage text can start with markup like `*Thunder* rolls.`
+//! Conditions compare a stat to a number with `<`, `<=`, `>`, `>=` or `==`.
+//! `author:`, `version:`, `description:`, `language:` and `license:` fill in the game's metadata,
+//! every `warning:` adds a content warning and every `intro:` a line of the intro.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use regex::Regex;
+
+use crate::game_components::*;
+use crate::game_state::GameState;
+use crate::ids::Id;
+use crate::locale::Localized;
+
+#[derive(Debug)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+lazy_static! {
+    static ref CONDITION: Regex = Regex::new(r"^if\s+(.+?)\s*(==|=|!=|<=|>=|<|>)\s*(-?\d+)$").unwrap();
+    static ref STAT_EFFECT: Regex = Regex::new(r"^(.+?)\s*(\+=|-=|=|\+|-)\s*(-?\d+)$").unwrap();
+}
+
+/// A name that still has to be looked up, with the line it was written in.
+struct Reference {
+    name: String,
+    line: usize,
+}
+
+enum RawCondition {
+    Always,
+    Compare { stat: Reference, op: String, value: StatValue },
+}
+
+enum RawEffect {
+    NoEffect,
+    Stat { stat: Reference, op: String, value: StatValue },
+    UseItem(Reference),
+    Unlock(Reference),
+}
+
+struct RawOption {
+    line: usize,
+    text: Vec<String>,
+    target: Option<Reference>,
+    condition: RawCondition,
+    effect: RawEffect,
+}
+
+struct RawStage {
+    line: usize,
+    name: String,
+    text: Vec<String>,
+    options: Vec<RawOption>,
+}
+
+enum RawItemEffect {
+    NoEffect,
+    Consumable(RawEffect),
+    Equippable { slot: String, when_equipped: RawEffect, when_unequipped: RawEffect },
+}
+
+struct RawItem {
+    line: usize,
+    name: String,
+    effect: RawItemEffect,
+}
+
+enum RawTrigger {
+    OnEffect,
+    Live(RawCondition),
+    OnFinish(RawCondition),
+}
+
+struct RawAchievement {
+    line: usize,
+    name: String,
+    description: String,
+    trigger: RawTrigger,
+}
+
+/// The block that indented header lines belong to.
+enum Block {
+    None,
+    Item,
+    Achievement,
+}
+
+#[derive(Default)]
+struct Script {
+    title: Option<String>,
+    metadata: Metadata,
+    warnings: Vec<String>,
+    intro: Vec<String>,
+    entry: Option<Reference>,
+    exit: Option<Reference>,
+    allow_undo: Option<bool>,
+    stats: Vec<(usize, String, StatValue)>,
+    slots: Vec<String>,
+    items: Vec<RawItem>,
+    achievements: Vec<RawAchievement>,
+    stages: Vec<RawStage>,
+}
+
+struct Parser {
+    script: Script,
+    errors: Vec<ScriptError>,
+}
+
+/// Splits a line into its text and the contents of its `{...}` commands.
+/// Template placeholders like `${stat.HP}` are part of the text.
+fn split_commands(line: &str) -> Result<(String, Vec<String>), String> {
+    let mut text = String::new();
+    let mut commands = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '$' if chars.peek() == Some(&'{') => {
+                text.push(c);
+                for c in chars.by_ref() {
+                    text.push(c);
+                    if c == '}' {
+                        break;
+                    }
+                }
+            }
+            '{' => {
+                let mut command = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => command.push(c),
+                        None => return Err("Missing '}'.".to_string()),
+                    }
+                }
+                commands.push(command.trim().to_string());
+            }
+            _ => text.push(c),
+        }
+    }
+    Ok((text, commands))
+}
+
+fn parse_value(value: &str) -> Result<StatValue, String> {
+    value.parse::<StatValue>().map_err(|_| format!("\"{}\" is not a valid stat value.", value))
+}
+
+fn parse_condition(command: &str, line: usize) -> Result<RawCondition, String> {
+    let captures = CONDITION.captures(command)
+        .ok_or(format!("\"{{{}}}\" is not a condition, write e.g. {{if HP < 3}}.", command))?;
+    let value = parse_value(&captures[3])?;
+    // Games only check for lower, higher and equal stats, so the rest is written with those.
+    let (op, value) = match &captures[2] {
+        "<=" => ("<", value.checked_add(1)),
+        ">=" => (">", value.checked_sub(1)),
+        "!=" => return Err(format!("\"{{{}}}\" uses the unsupported operator \"!=\", \
+                                    options can only compare a stat with <, <=, >, >= or ==.", command)),
+        op => (op, Some(value)),
+    };
+    Ok(RawCondition::Compare {
+        stat: Reference { name: captures[1].to_string(), line },
+        op: op.to_string(),
+        value: value.ok_or(format!("\"{}\" is out of range in \"{{{}}}\".", &captures[3], command))?,
+    })
+}
+
+fn parse_effect(command: &str, line: usize) -> Result<RawEffect, String> {
+    if let Some(item) = command.strip_prefix("use ") {
+        return Ok(RawEffect::UseItem(Reference { name: item.trim().to_string(), line }));
+    }
+    if let Some(achievement) = command.strip_prefix("unlock ") {
+        return Ok(RawEffect::Unlock(Reference { name: achievement.trim().to_string(), line }));
+    }
+    let captures = STAT_EFFECT.captures(command)
+        .ok_or(format!("\"{{{}}}\" is not an effect, write e.g. {{HP += 3}} or {{use Potion}}.", command))?;
+    Ok(RawEffect::Stat {
+        stat: Reference { name: captures[1].to_string(), line },
+        op: captures[2].to_string(),
+        value: parse_value(&captures[3])?,
+    })
+}
+
+/// Parses a single `{...}` command of a header block line, like `when_equipped {HP += 1}`.
+fn single_command(rest: &str) -> Result<String, String> {
+    let (text, mut commands) = split_commands(rest)?;
+    if !text.trim().is_empty() || commands.len() != 1 {
+        return Err(format!("Expected exactly one {{...}}, found \"{}\".", rest.trim()));
+    }
+    Ok(commands.remove(0))
+}
+
+impl Parser {
+    fn error(&mut self, line: usize, message: String) {
+        self.errors.push(ScriptError { line, message });
+    }
+
+    /// Parses the whole script, collecting every error instead of stopping at the first one.
+    fn parse(mut self, text: &str) -> (Script, Vec<ScriptError>) {
+        let mut block = Block::None;
+        for (i, raw_line) in text.lines().enumerate() {
+            let line_nr = i + 1;
+            let trimmed = raw_line.trim();
+            if trimmed.starts_with("//") {
+                continue;
+            }
+            let indented = raw_line.starts_with(|c: char| c.is_whitespace());
+            let result = if trimmed.starts_with("===") {
+                self.start_stage(trimmed, line_nr)
+            } else if !self.script.stages.is_empty() {
+                self.stage_line(raw_line, indented, line_nr)
+            } else if trimmed.is_empty() {
+                Ok(())
+            } else if indented {
+                self.block_line(&block, trimmed, line_nr)
+            } else {
+                self.header_line(trimmed, line_nr).map(|new_block| block = new_block)
+            };
+            if let Err(message) = result {
+                self.error(line_nr, message);
+            }
+        }
+        if self.script.stages.is_empty() {
+            self.error(1, "There are no stages, start one with \"=== Stage name\".".to_string());
+        }
+        for stage in self.script.stages.iter_mut() {
+            while stage.text.last().is_some_and(|line| line.is_empty()) {
+                stage.text.pop();
+            }
+        }
+        (self.script, self.errors)
+    }
+
+    fn header_line(&mut self, line: &str, line_nr: usize) -> Result<Block, String> {
+        let (keyword, rest) = match line.find(|c: char| c.is_whitespace() || c == ':') {
+            Some(end) => (&line[..end], line[end..].trim_start_matches(':').trim()),
+            None => (line, ""),
+        };
+        if rest.is_empty() {
+            return Err(format!("\"{}\" needs a value.", keyword));
+        }
+        let script = &mut self.script;
+        match keyword {
+            "title" => script.title = Some(rest.to_string()),
+            "author" => script.metadata.author = Some(rest.to_string()),
+            "version" => script.metadata.version = Some(rest.to_string()),
+            "description" => script.metadata.description = Some(Localized::new(rest.to_string())),
+            "language" => script.metadata.language = Some(rest.to_string()),
+            "license" => script.metadata.license = Some(rest.to_string()),
+            "warning" => script.warnings.push(rest.to_string()),
+            "intro" => script.intro.push(rest.to_string()),
+            "entry" => script.entry = Some(Reference { name: rest.to_string(), line: line_nr }),
+            "exit" => script.exit = Some(Reference { name: rest.to_string(), line: line_nr }),
+            "undo" => script.allow_undo = Some(match rest {
+                "on" => true,
+                "off" => false,
+                _ => return Err(format!("undo can be on or off, not \"{}\".", rest)),
+            }),
+            "stat" => {
+                let captures = STAT_EFFECT.captures(rest).filter(|captures| &captures[2] == "=")
+                    .ok_or(format!("\"{}\" is not a stat, write e.g. stat HP = 10.", rest))?;
+                script.stats.push((line_nr, captures[1].to_string(), parse_value(&captures[3])?));
+            }
+            "slot" => script.slots.push(rest.to_string()),
+            "item" => {
+                script.items.push(RawItem { line: line_nr, name: rest.to_string(), effect: RawItemEffect::NoEffect });
+                return Ok(Block::Item);
+            }
+            "achievement" => {
+                script.achievements.push(RawAchievement {
+                    line: line_nr,
+                    name: rest.to_string(),
+                    description: String::new(),
+                    trigger: RawTrigger::OnEffect,
+                });
+                return Ok(Block::Achievement);
+            }
+            _ => return Err(format!("Unknown keyword \"{}\".", keyword)),
+        }
+        Ok(Block::None)
+    }
+
+    /// An indented line below an item or an achievement.
+    fn block_line(&mut self, block: &Block, line: &str, line_nr: usize) -> Result<(), String> {
+        let (keyword, rest) = match line.find(|c: char| c.is_whitespace() || c == ':') {
+            Some(end) => (&line[..end], line[end..].trim_start_matches(':').trim()),
+            None => (line, ""),
+        };
+        match block {
+            Block::None => Err("Indented lines are only allowed below items and achievements.".to_string()),
+            Block::Item => {
+                let item = self.script.items.last_mut().unwrap();
+                match keyword {
+                    "equip" if !rest.is_empty() => item.effect = RawItemEffect::Equippable {
+                        slot: rest.to_string(),
+                        when_equipped: RawEffect::NoEffect,
+                        when_unequipped: RawEffect::NoEffect,
+                    },
+                    "consume" => item.effect = RawItemEffect::Consumable(parse_effect(&single_command(rest)?, line_nr)?),
+                    "when_equipped" | "when_unequipped" => match item.effect {
+                        RawItemEffect::Equippable { ref mut when_equipped, ref mut when_unequipped, .. } => {
+                            let effect = parse_effect(&single_command(rest)?, line_nr)?;
+                            if keyword == "when_equipped" { *when_equipped = effect } else { *when_unequipped = effect }
+                        }
+                        _ => return Err(format!("{} needs an \"equip slot\" line before it.", keyword)),
+                    },
+                    _ => return Err(format!("Items can have equip, when_equipped, when_unequipped or consume, not \"{}\".", line)),
+                }
+                Ok(())
+            }
+            Block::Achievement => {
+                let achievement = self.script.achievements.last_mut().unwrap();
+                let condition = || -> Result<RawCondition, String> {
+                    if rest.is_empty() { Ok(RawCondition::Always) } else { parse_condition(&single_command(rest)?, line_nr) }
+                };
+                match keyword {
+                    "description" => achievement.description = rest.to_string(),
+                    "live" => achievement.trigger = RawTrigger::Live(condition()?),
+                    "on_finish" => achievement.trigger = RawTrigger::OnFinish(condition()?),
+                    _ => return Err(format!("Achievements can have description, live or on_finish, not \"{}\".", line)),
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn start_stage(&mut self, line: &str, line_nr: usize) -> Result<(), String> {
+        let name = line.trim_start_matches('=').trim_end_matches('=').trim();
+        if name.is_empty() {
+            return Err("A stage needs a name, write e.g. \"=== Stage 1\".".to_string());
+        }
+        self.script.stages.push(RawStage { line: line_nr, name: name.to_string(), text: Vec::new(), options: Vec::new() });
+        Ok(())
+    }
+
+    fn stage_line(&mut self, raw_line: &str, indented: bool, line_nr: usize) -> Result<(), String> {
+        let stage = self.script.stages.last_mut().unwrap();
+        let trimmed = raw_line.trim();
+        // `*Thunder* rolls.` is stage text with markup, only `* ` starts an option.
+        let option_text = trimmed.strip_prefix('*')
+            .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace));
+        if let Some(option_text) = option_text {
+            stage.options.push(RawOption {
+                line: line_nr,
+                text: Vec::new(),
+                target: None,
+                condition: RawCondition::Always,
+                effect: RawEffect::NoEffect,
+            });
+            return Parser::option_line(stage.options.last_mut().unwrap(), option_text.trim(), line_nr);
+        }
+        if trimmed.is_empty() {
+            if stage.options.is_empty() && !stage.text.is_empty() {
+                stage.text.push(String::new());
+            }
+            return Ok(());
+        }
+        match stage.options.last_mut() {
+            Some(option) if indented => Parser::option_line(option, trimmed, line_nr),
+            Some(_) => Err("Stage text has to come before the options. Indent the line to continue the option.".to_string()),
+            None => {
+                stage.text.push(trimmed.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    fn option_line(option: &mut RawOption, line: &str, line_nr: usize) -> Result<(), String> {
+        let (text, commands) = split_commands(line)?;
+        let text = match text.rfind("->") {
+            Some(arrow) => {
+                if option.target.is_some() {
+                    return Err("This option already has a target.".to_string());
+                }
+                let target = text[arrow + 2..].trim();
+                if target.is_empty() {
+                    return Err("Missing stage name after \"->\".".to_string());
+                }
+                option.target = Some(Reference { name: target.to_string(), line: line_nr });
+                text[..arrow].trim().to_string()
+            }
+            None => text.trim().to_string(),
+        };
+        if !text.is_empty() {
+            option.text.push(text);
+        }
+        for command in commands {
+            if command.starts_with("if ") {
+                if let RawCondition::Compare { .. } = option.condition {
+                    return Err("An option can have only one condition.".to_string());
+                }
+                option.condition = parse_condition(&command, line_nr)?;
+            } else {
+                if let RawEffect::NoEffect = option.effect {} else {
+                    return Err("An option can have only one effect.".to_string());
+                }
+                option.effect = parse_effect(&command, line_nr)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turns the parsed script into a game, looking up all names.
+struct Resolver {
+    stats: HashMap<String, usize>,
+    stages: HashMap<String, usize>,
+    items: HashMap<String, usize>,
+    achievements: HashMap<String, usize>,
+    errors: Vec<ScriptError>,
+}
+
+impl Resolver {
+    fn index<'a, I: Iterator<Item=(usize, &'a String)>>(&mut self, kind: &str, names: I) -> HashMap<String, usize> {
+        let mut map = HashMap::new();
+        for (i, (line, name)) in names.enumerate() {
+            if map.insert(name.clone(), i).is_some() {
+                self.errors.push(ScriptError { line, message: format!("There already is a {} called \"{}\".", kind, name) });
+            }
+        }
+        map
+    }
+
+    fn find(&mut self, map: fn(&Resolver) -> &HashMap<String, usize>, kind: &str, reference: &Reference) -> Id {
+        match map(self).get(&reference.name) {
+            Some(id) => Id::from(*id),
+            None => {
+                self.errors.push(ScriptError {
+                    line: reference.line,
+                    message: format!("There is no {} called \"{}\".", kind, reference.name),
+                });
+                Id::default()
+            }
+        }
+    }
+
+    fn stat(&mut self, reference: &Reference) -> Id { self.find(|r| &r.stats, "stat", reference) }
+
+    fn condition(&mut self, condition: &RawCondition) -> Condition {
+        match condition {
+            RawCondition::Always => Condition::Always,
+            RawCondition::Compare { stat, op, value } => {
+                let stat_id = self.stat(stat);
+                match &op[..] {
+                    "<" => Condition::IfStatLower { stat_id, lower_than: *value },
+                    ">" => Condition::IfStatHigher { stat_id, higher_than: *value },
+                    _ => Condition::IfStatExact { stat_id, value: *value },
+                }
+            }
+        }
+    }
+
+    fn effect(&mut self, effect: &RawEffect) -> Effect {
+        match effect {
+            RawEffect::NoEffect => Effect::NoEffect,
+            RawEffect::Stat { stat, op, value } => {
+                let stat_id = self.stat(stat);
+                match &op[..] {
+                    "+" | "+=" => Effect::SetStatHigher { stat_id, to_add: *value },
+                    "-" | "-=" => Effect::SetStatLower { stat_id, to_subtract: *value },
+                    _ => Effect::SetStatExact { stat_id, new_value: *value },
+                }
+            }
+            RawEffect::UseItem(item) => Effect::UseItem { item_id: self.find(|r| &r.items, "item", item) },
+            RawEffect::Unlock(achievement) =>
+                Effect::UnlockAchievement { achievement_id: self.find(|r| &r.achievements, "achievement", achievement) },
+        }
+    }
+
+    fn resolve(mut self, script: Script) -> Result<GameState, Vec<ScriptError>> {
+        self.stats = self.index("stat", script.stats.iter().map(|(line, name, _)| (*line, name)));
+        self.stages = self.index("stage", script.stages.iter().map(|stage| (stage.line, &stage.name)));
+        self.items = self.index("item", script.items.iter().map(|item| (item.line, &item.name)));
+        self.achievements = self.index("achievement", script.achievements.iter().map(|a| (a.line, &a.name)));
+
+        let mut game = GameState::new(script.title.clone().unwrap_or_default());
+        if script.title.is_none() {
+            self.errors.push(ScriptError { line: 1, message: "The game needs a \"title: ...\" line.".to_string() });
+        }
+        game.metadata = Metadata {
+            content_warnings: Localized::new(script.warnings.clone()),
+            intro: Localized::new(script.intro.clone()),
+            ..script.metadata.clone()
+        };
+        game.allow_undo = script.allow_undo.unwrap_or(game.allow_undo);
+        game.stats = script.stats.iter().enumerate()
+            .map(|(id, (_, name, value))| Statistic { id: Id::from(id), name: Localized::new(name.clone()), value: *value })
+            .collect();
+        game.item_slots = script.slots.clone();
+        for item in script.items.iter() {
+            if let RawItemEffect::Equippable { ref slot, .. } = item.effect {
+                if !game.item_slots.contains(slot) {
+                    game.item_slots.push(slot.clone());
+                }
+            }
+        }
+        game.items = script.items.iter().enumerate().map(|(id, item)| Item {
+            id: Id::from(id),
+            name: Localized::new(item.name.clone()),
+            effect: match &item.effect {
+                RawItemEffect::NoEffect => ItemEffect::NoEffect,
+                RawItemEffect::Consumable(effect) => ItemEffect::Consumable { on_consume: self.effect(effect) },
+                RawItemEffect::Equippable { slot, when_equipped, when_unequipped } => ItemEffect::Equippable {
+                    slot: slot.clone(),
+                    when_equipped: self.effect(when_equipped),
+                    when_unequipped: self.effect(when_unequipped),
+                },
+            },
+            in_use: false,
+        }).collect();
+        game.achievements = script.achievements.iter().enumerate().map(|(id, achievement)| Achievement {
+            id: Id::from(id),
+            name: Localized::new(achievement.name.clone()),
+            description: Localized::new(achievement.description.clone()),
+            trigger: match &achievement.trigger {
+                RawTrigger::OnEffect => AchievementTrigger::OnEffect,
+                RawTrigger::Live(condition) => AchievementTrigger::Live { condition: self.condition(condition) },
+                RawTrigger::OnFinish(condition) => AchievementTrigger::OnFinish { condition: self.condition(condition) },
+            },
+        }).collect();
+
+        for (index, raw_stage) in script.stages.iter().enumerate() {
+            let mut stage = Stage::new();
+            stage.index = Id::from(index);
+            stage.name = Localized::new(raw_stage.name.clone());
+            stage.text = Localized::new(raw_stage.text.clone());
+            for raw_option in raw_stage.options.iter() {
+                let target_stage = match raw_option.target {
+                    Some(ref target) => self.find(|r| &r.stages, "stage", target),
+                    None => {
+                        self.errors.push(ScriptError {
+                            line: raw_option.line,
+                            message: "This option doesn't lead anywhere, add \"-> Stage name\".".to_string(),
+                        });
+                        Id::default()
+                    }
+                };
+                stage.options.push(StageOption {
+                    target_stage,
+                    text: Localized::new(raw_option.text.clone()),
+                    condition: self.condition(&raw_option.condition),
+                    effect: self.effect(&raw_option.effect),
+                });
+            }
+            game.stages.push(stage);
+        }
+
+        game.entry_stage = match script.entry {
+            Some(ref entry) => self.find(|r| &r.stages, "stage", entry),
+            None => Id::default(),
+        };
+        game.exit_stage = match script.exit {
+            Some(ref exit) => self.find(|r| &r.stages, "stage", exit),
+            None => {
+                let endings: Vec<&RawStage> = script.stages.iter().filter(|stage| stage.options.is_empty()).collect();
+                if endings.len() != 1 {
+                    let line = endings.get(1).map_or(1, |stage| stage.line);
+                    self.errors.push(ScriptError {
+                        line,
+                        message: format!("Found {} stages without options, there has to be exactly one.", endings.len()),
+                    });
+                }
+                endings.first().map_or(Id::default(), |ending| Id::from(self.stages[&ending.name]))
+            }
+        };
+
+        if self.errors.is_empty() {
+            Ok(game)
+        } else {
+            self.errors.sort_by_key(|error| error.line);
+            Err(self.errors)
+        }
+    }
+}
+
+/// Compiles a story script into a game that still has to be post processed, like one read from JSON.
+pub fn compile(text: &str) -> Result<GameState, Vec<ScriptError>> {
+    let (script, errors) = Parser { script: Script::default(), errors: Vec::new() }.parse(text);
+    Resolver {
+        stats: HashMap::new(),
+        stages: HashMap::new(),
+        items: HashMap::new(),
+        achievements: HashMap::new(),
+        errors,
+    }.resolve(script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(text: &str) -> Vec<String> {
+        compile(text).unwrap_err().iter().map(ToString::to_string).collect()
+    }
+
+    const GAME: &str = "title: Test\n\
+                        stat HP = 2\n\
+                        === Start\n\
+                        *Thunder* rolls.\n\
+                        * Rest. -> Start {if HP <= 2}\n\
+                        * Fight. -> End {if HP >= 1}\n\
+                        * Run. -> End {if HP == 0}\n\
+                        === End\n\
+                        The end.\n";
+
+    #[test]
+    fn compiles_stages_and_options() {
+        let game = compile(GAME).unwrap();
+        assert_eq!(game.name, "Test");
+        assert_eq!(game.stages.len(), 2);
+        let start = &game.stages[0];
+        assert_eq!(start.text.to_vec(), vec!["*Thunder* rolls.".to_string()]);
+        assert_eq!(start.options.len(), 3);
+        assert_eq!(start.options[0].target_stage, Id::Number(0));
+        assert_eq!(start.options[1].target_stage, Id::Number(1));
+        assert!(game.stages[1].options.is_empty());
+    }
+
+    #[test]
+    fn rewrites_at_most_and_at_least() {
+        let game = compile(GAME).unwrap();
+        let conditions: Vec<&Condition> = game.stages[0].options.iter().map(|option| &option.condition).collect();
+        assert!(matches!(conditions[0], Condition::IfStatLower { lower_than: 3, .. }));
+        assert!(matches!(conditions[1], Condition::IfStatHigher { higher_than: 0, .. }));
+        assert!(matches!(conditions[2], Condition::IfStatExact { value: 0, .. }));
+    }
+
+    #[test]
+    fn rejects_not_equal_and_out_of_range_bounds() {
+        let script = GAME.replace("HP == 0", "HP != 0");
+        assert_eq!(errors(&script), vec!["line 7: \"{if HP != 0}\" uses the unsupported operator \"!=\", \
+                                          options can only compare a stat with <, <=, >, >= or ==.".to_string()]);
+        let script = GAME.replace("HP <= 2", "HP <= 2147483647");
+        assert_eq!(errors(&script), vec!["line 5: \"2147483647\" is out of range in \"{if HP <= 2147483647}\"."
+            .to_string()]);
+    }
+
+    #[test]
+    fn reports_unknown_names() {
+        let script = GAME.replace("-> End {if HP >= 1}", "-> Nowhere {if Mana >= 1}");
+        assert_eq!(errors(&script), vec!["line 6: There is no stage called \"Nowhere\".".to_string(),
+                                         "line 6: There is no stat called \"Mana\".".to_string()]);
+    }
+}
@@ -0,0 +1,833 @@
+//! Texts players see can show the state of the game through placeholders between `${` and `}`:
+//!
+//! - `${stat.Gold}` shows the value of a stat, `${item.Sword}` the name of an item. Stats and items are
+//!   named, in any of their languages, or numbered by their position in the game file, like `${item.0}`.
+//!   Names with characters that mean something in a placeholder are quoted, like `${stat."Hit-points"}`.
+//! - `${item.Sword.slot}` shows the slot an item is equipped in, `${equipped.weapon}` the name of the item
+//!   in a slot, or "nothing". `${inventory}` lists the items the player holds, see `GameState::inventory`.
+//! - `${stage.name}` shows the name of the stage the player is in.
+//! - `${stat.Gold * 2 + 1}` shows the result of `+`, `-`, `*`, `/` and `%` on whole numbers.
+//! - `${plural stat.Gold "coin" "coins"}` shows the first word if the number is 1 or -1, the second one otherwise.
+//! - `${if stat.HP < 3}You are badly hurt.${else}You feel fine.${end}` shows a part of the text only if
+//!   a condition holds. Conditions compare with `<`, `<=`, `>`, `>=`, `=` and `!=`,
+//!   and are combined with `and`, `or` and `not`. The `${else}` part can be left out.
+//! - `$$` shows a single `$`. A `$` that isn't followed by `{` is shown as it is.
+//!
+//! Around placeholders, text can be styled, see `markup`. Item names can be templates too, styled or not,
+//! but can't show items themselves.
+//!
+//! Texts are compiled when the game is loaded, see `GameState::post_process`,
+//! and `diagnostics::validate` reports those that don't compile.
+
+use std::fmt;
+
+use crate::game_components::{Item, ItemEffect};
+use crate::game_state::GameState;
+use crate::locale::Localized;
+use crate::markup::{self, Mark, Piece, Span, Style};
+
+/// What an expression works out to, checked when it's compiled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Number,
+    Text,
+    Condition,
+}
+
+impl Type {
+    fn plural(self) -> &'static str {
+        match self {
+            Type::Number => "numbers",
+            Type::Text => "names",
+            Type::Condition => "conditions",
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Number => write!(f, "a number"),
+            Type::Text => write!(f, "a name"),
+            Type::Condition => write!(f, "a condition"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+/// Operators as they're written, longer ones first so `<=` isn't read as `<`.
+const COMPARISONS: [(&str, Operator); 7] = [
+    ("<=", Operator::LessOrEqual), (">=", Operator::GreaterOrEqual), ("!=", Operator::NotEqual),
+    ("==", Operator::Equal), ("=", Operator::Equal), ("<", Operator::Less), (">", Operator::Greater),
+];
+const SUMS: [(&str, Operator); 2] = [("+", Operator::Add), ("-", Operator::Subtract)];
+const PRODUCTS: [(&str, Operator); 3] = [("*", Operator::Multiply), ("/", Operator::Divide), ("%", Operator::Remainder)];
+
+/// What's shown for an empty slot or inventory.
+const NOTHING: &str = "nothing";
+
+/// Characters that end a name that isn't quoted.
+const SPECIAL: &[char] = &['(', ')', '.', '"', '+', '-', '*', '/', '%', '<', '>', '=', '!'];
+
+#[derive(Debug, Clone)]
+enum Expression {
+    Number(i64),
+    Text(String),
+    /// A stat by its position.
+    Stat(usize),
+    /// An item by its position.
+    Item(usize),
+    /// The item in a slot.
+    Equipped(String),
+    Inventory,
+    StageName,
+    Negate(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(i64),
+    Text(String),
+    Condition(bool),
+}
+
+impl Value {
+    fn number(self) -> Option<i64> {
+        match self {
+            Value::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    fn condition(self) -> Option<bool> {
+        match self {
+            Value::Condition(condition) => Some(condition),
+            _ => None,
+        }
+    }
+}
+
+impl Expression {
+    /// What the expression works out to in the game as it is now,
+    /// or nothing if it can't be worked out, like when dividing by zero.
+    fn evaluate(&self, game: &GameState) -> Option<Value> {
+        Some(match self {
+            Expression::Number(number) => Value::Number(*number),
+            Expression::Text(text) => Value::Text(text.clone()),
+            Expression::Stat(position) => Value::Number(game.stats.get(*position)?.value as i64),
+            Expression::Item(position) => Value::Text(game.parse_format_text(&game.items.get(*position)?.name)),
+            Expression::Equipped(_) | Expression::Inventory => Value::Text(markup::plain(&self.items(game))),
+            Expression::StageName => Value::Text(markup::strip(&game.get_current_stage().name)),
+            Expression::Negate(value) => Value::Number(value.evaluate(game)?.number()?.checked_neg()?),
+            Expression::Not(value) => Value::Condition(!value.evaluate(game)?.condition()?),
+            Expression::Binary(left, Operator::And, right) => Value::Condition(
+                left.evaluate(game)?.condition()? && right.evaluate(game)?.condition()?
+            ),
+            Expression::Binary(left, Operator::Or, right) => Value::Condition(
+                left.evaluate(game)?.condition()? || right.evaluate(game)?.condition()?
+            ),
+            Expression::Binary(left, Operator::Equal, right) =>
+                Value::Condition(left.evaluate(game)? == right.evaluate(game)?),
+            Expression::Binary(left, Operator::NotEqual, right) =>
+                Value::Condition(left.evaluate(game)? != right.evaluate(game)?),
+            Expression::Binary(left, operator, right) => {
+                let left = left.evaluate(game)?.number()?;
+                let right = right.evaluate(game)?.number()?;
+                match operator {
+                    Operator::Add => Value::Number(left.checked_add(right)?),
+                    Operator::Subtract => Value::Number(left.checked_sub(right)?),
+                    Operator::Multiply => Value::Number(left.checked_mul(right)?),
+                    Operator::Divide => Value::Number(left.checked_div(right)?),
+                    Operator::Remainder => Value::Number(left.checked_rem(right)?),
+                    Operator::Less => Value::Condition(left < right),
+                    Operator::LessOrEqual => Value::Condition(left <= right),
+                    Operator::Greater => Value::Condition(left > right),
+                    Operator::GreaterOrEqual => Value::Condition(left >= right),
+                    Operator::Equal | Operator::NotEqual | Operator::And | Operator::Or => unreachable!(),
+                }
+            }
+        })
+    }
+
+    /// The names of the items the expression shows, styled.
+    fn items(&self, game: &GameState) -> Vec<Span> {
+        let items: Vec<&Item> = match self {
+            Expression::Item(position) => game.items.get(*position).into_iter().collect(),
+            Expression::Equipped(slot) => game.equipped(slot).into_iter().collect(),
+            Expression::Inventory => game.inventory().collect(),
+            _ => Vec::new(),
+        };
+        if items.is_empty() {
+            return vec![Span { text: NOTHING.to_string(), style: Style::default() }];
+        }
+        let mut spans = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                markup::push(&mut spans, ", ", Style::default());
+            }
+            for span in game.format_text(&item.name) {
+                markup::push(&mut spans, &span.text, span.style);
+            }
+        }
+        spans
+    }
+
+    /// Whether the expression shows the names of items.
+    fn shows_items(&self) -> bool {
+        matches!(self, Expression::Item(_) | Expression::Equipped(_) | Expression::Inventory)
+    }
+
+    fn visit(&self, f: &mut dyn FnMut(&Expression)) {
+        f(self);
+        match self {
+            Expression::Negate(value) | Expression::Not(value) => value.visit(f),
+            Expression::Binary(left, _, right) => {
+                left.visit(f);
+                right.visit(f);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Mark(Mark),
+    /// An expression to show, with the placeholder as written, shown instead if it can't be worked out.
+    Show(Expression, String),
+    Plural { count: Expression, one: String, many: String, source: String },
+    If { condition: Expression, then: Vec<Node>, otherwise: Vec<Node> },
+}
+
+/// A compiled text.
+#[derive(Debug, Clone, Default)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+fn render_nodes(nodes: &[Node], game: &GameState, style: &mut Style, spans: &mut Vec<Span>) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => markup::push(spans, text, *style),
+            Node::Mark(mark) => mark.apply(style),
+            // Shown in their own style, see `Template::shows_items` for why this ends.
+            Node::Show(expression, _) if expression.shows_items() => for span in expression.items(game) {
+                markup::push(spans, &span.text, span.style.within(*style));
+            },
+            Node::Show(expression, source) => match expression.evaluate(game) {
+                Some(Value::Number(number)) => markup::push(spans, &number.to_string(), *style),
+                Some(Value::Text(text)) => markup::push(spans, &text, *style),
+                _ => markup::push(spans, source, *style),
+            },
+            Node::Plural { count, one, many, source } => match count.evaluate(game).and_then(Value::number) {
+                Some(1) | Some(-1) => markup::push(spans, one, *style),
+                Some(_) => markup::push(spans, many, *style),
+                None => markup::push(spans, source, *style),
+            },
+            Node::If { condition, then, otherwise } =>
+                if condition.evaluate(game).and_then(Value::condition).unwrap_or(false) {
+                    render_nodes(then, game, style, spans);
+                } else {
+                    render_nodes(otherwise, game, style, spans);
+                },
+        }
+    }
+}
+
+fn visit_nodes(nodes: &[Node], f: &mut dyn FnMut(&Expression)) {
+    for node in nodes {
+        match node {
+            Node::Text(_) | Node::Mark(_) => {}
+            Node::Show(expression, _) | Node::Plural { count: expression, .. } => expression.visit(f),
+            Node::If { condition, then, otherwise } => {
+                condition.visit(f);
+                visit_nodes(then, f);
+                visit_nodes(otherwise, f);
+            }
+        }
+    }
+}
+
+impl Template {
+    /// The text as it should be shown in the game as it is now.
+    pub fn render(&self, game: &GameState) -> Vec<Span> {
+        let mut spans = Vec::new();
+        render_nodes(&self.nodes, game, &mut Style::default(), &mut spans);
+        spans
+    }
+
+    /// The positions of the stats the text shows or depends on.
+    pub fn stats(&self) -> Vec<usize> {
+        let mut stats = Vec::new();
+        visit_nodes(&self.nodes, &mut |expression| if let Expression::Stat(position) = expression {
+            stats.push(*position);
+        });
+        stats
+    }
+
+    /// Whether the text shows the name of any item, which item names can't, so they can't show themselves.
+    pub fn shows_items(&self) -> bool {
+        let mut shows = false;
+        visit_nodes(&self.nodes, &mut |expression| shows |= expression.shows_items());
+        shows
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Where the `}` closing a placeholder is, skipping those in quoted texts.
+fn placeholder_end(text: &str) -> Option<usize> {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '}' if !quoted => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+type Parsed = Result<(Expression, Type), String>;
+
+/// Reads what's between `${` and `}`.
+struct Parser<'a> {
+    game: &'a GameState,
+    placeholder: &'a str,
+    position: usize,
+    /// Problems that don't keep the rest of the text from being read, like unknown names.
+    errors: Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(game: &'a GameState, placeholder: &'a str) -> Parser<'a> {
+        Parser { game, placeholder, position: 0, errors: Vec::new() }
+    }
+
+    /// What's left to read, after skipping whitespace.
+    fn rest(&mut self) -> &'a str {
+        let rest = &self.placeholder[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+        &self.placeholder[self.position..]
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found = self.rest().starts_with(symbol);
+        if found {
+            self.position += symbol.len();
+        }
+        found
+    }
+
+    /// Like `eat`, but only if the word isn't the start of a longer one.
+    fn eat_word(&mut self, word: &str) -> bool {
+        let rest = self.rest();
+        let found = rest.starts_with(word) && !rest[word.len()..].starts_with(is_name_char);
+        if found {
+            self.position += word.len();
+        }
+        found
+    }
+
+    fn eat_operator(&mut self, operators: &[(&'static str, Operator)]) -> Option<(&'static str, Operator)> {
+        let rest = self.rest();
+        let &(symbol, operator) = operators.iter().find(|(symbol, _)| rest.starts_with(symbol))?;
+        self.position += symbol.len();
+        Some((symbol, operator))
+    }
+
+    /// What comes next, to say what was found instead of what was expected.
+    fn found(&mut self) -> String {
+        match self.rest() {
+            "" => "the end of the placeholder".to_string(),
+            rest => format!("\"{}\"", rest),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if !self.eat("\"") {
+            return Err(format!("expected a quoted text, like \"coins\", but found {}", self.found()));
+        }
+        let mut text = String::new();
+        let mut chars = self.placeholder[self.position..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.position += i + 1;
+                    return Ok(text);
+                }
+                '\\' => text.extend(chars.next().map(|(_, c)| c)),
+                _ => text.push(c),
+            }
+        }
+        Err("a quoted text is never closed with \"".to_string())
+    }
+
+    /// The name of a stat or item after the dot, quoted, or up to the next operator, `and` or `or`.
+    fn name(&mut self) -> Result<String, String> {
+        let rest = self.rest();
+        if rest.starts_with('"') {
+            return self.string();
+        }
+        let run = &rest[..rest.find(SPECIAL).unwrap_or(rest.len())];
+        let mut end = 0;
+        for word in run.split_whitespace() {
+            if word == "and" || word == "or" {
+                break;
+            }
+            end += run[end..].find(word).unwrap_or(0) + word.len();
+        }
+        self.position += end;
+        match run[..end].trim() {
+            "" => Err(format!("expected a name or a number after the dot, but found {}", self.found())),
+            name => Ok(name.to_string()),
+        }
+    }
+
+    /// The position of the stat or item named or numbered `key`, reporting it if there's none.
+    fn find<'b, N>(&mut self, kind: &str, key: &str, mut names: N) -> usize
+        where N: Iterator<Item=&'b Localized<String>> {
+        let message = match key.parse::<usize>() {
+            Ok(position) => match names.count() {
+                count if position < count => return position,
+                0 => format!("There is no {} number {}, the game has no {}s.", kind, position, kind),
+                count => format!("There is no {} number {}, {}s are numbered from 0 to {}.",
+                                 kind, position, kind, count - 1),
+            },
+            // Styled names can be written without their markup.
+            Err(_) => match names.position(|name| name.has(key) || name.values().any(|name| markup::strip(name) == key)) {
+                Some(position) => return position,
+                None => format!("There is no {} named \"{}\".", kind, key),
+            },
+        };
+        self.errors.push(message);
+        0
+    }
+
+    fn primary(&mut self) -> Parsed {
+        let rest = self.rest();
+        if self.eat("(") {
+            let expression = self.expression()?;
+            if !self.eat(")") {
+                return Err(format!("expected \")\", but found {}", self.found()));
+            }
+            return Ok(expression);
+        }
+        if rest.starts_with('"') {
+            return Ok((Expression::Text(self.string()?), Type::Text));
+        }
+        if rest.starts_with(|c: char| c.is_ascii_digit()) {
+            let digits = &rest[..rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len())];
+            self.position += digits.len();
+            return digits.parse().map(|number| (Expression::Number(number), Type::Number))
+                .map_err(|_| format!("the number {} is too big", digits));
+        }
+        let word = &rest[..rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len())];
+        let game = self.game;
+        match word {
+            "stat" | "item" => {
+                self.position += word.len();
+                if !self.eat(".") {
+                    return Err(format!("expected a dot and the name of the {}, like \"{}.Name\", but found {}",
+                                       word, word, self.found()));
+                }
+                let name = self.name()?;
+                if word == "stat" {
+                    return Ok((Expression::Stat(self.find("stat", &name, game.stats.iter().map(|stat| &stat.name))),
+                               Type::Number));
+                }
+                let position = self.find("item", &name, game.items.iter().map(|item| &item.name));
+                if !self.eat(".") {
+                    return Ok((Expression::Item(position), Type::Text));
+                }
+                if !self.eat_word("slot") {
+                    return Err(format!("expected \"slot\" after the name of the item, but found {}", self.found()));
+                }
+                match game.items.get(position).map(|item| &item.effect) {
+                    Some(ItemEffect::Equippable { slot, .. }) => Ok((Expression::Text(slot.clone()), Type::Text)),
+                    _ => {
+                        self.errors.push(format!("The item \"{}\" can't be equipped, so it has no slot.", name));
+                        Ok((Expression::Text(String::new()), Type::Text))
+                    }
+                }
+            }
+            "equipped" => {
+                self.position += word.len();
+                if !self.eat(".") {
+                    return Err(format!("expected a dot and the name of the slot, like \"equipped.weapon\", \
+                                        but found {}", self.found()));
+                }
+                let slot = self.name()?;
+                if !game.item_slots.contains(&slot) {
+                    self.errors.push(format!("There is no item slot \"{}\".", slot));
+                }
+                Ok((Expression::Equipped(slot), Type::Text))
+            }
+            "inventory" => {
+                self.position += word.len();
+                Ok((Expression::Inventory, Type::Text))
+            }
+            "stage" => {
+                self.position += word.len();
+                if !(self.eat(".") && self.eat_word("name")) {
+                    return Err(format!("expected \".name\" after \"stage\", but found {}", self.found()));
+                }
+                Ok((Expression::StageName, Type::Text))
+            }
+            "" => Err(format!("expected a value, like \"stat.Name\" or a number, but found {}", self.found())),
+            _ => Err(format!("\"{}\" isn't something a placeholder can show, it can show \"stat.Name\", \
+                              \"item.Name\", \"item.Name.slot\", \"equipped.slot\", \"inventory\" \
+                              and \"stage.name\"", word)),
+        }
+    }
+
+    /// Reports it if an operand isn't of the type the operator works on.
+    fn check(&mut self, symbol: &str, found: Type, wanted: Type) {
+        if found != wanted {
+            self.errors.push(format!("\"{}\" works on {}, not on {}.", symbol, wanted.plural(), found));
+        }
+    }
+
+    fn unary(&mut self) -> Parsed {
+        if self.eat("-") {
+            let (value, found) = self.unary()?;
+            self.check("-", found, Type::Number);
+            return Ok((Expression::Negate(Box::new(value)), Type::Number));
+        }
+        self.primary()
+    }
+
+    fn arithmetic(&mut self, operators: &[(&'static str, Operator)], operand: fn(&mut Parser<'a>) -> Parsed) -> Parsed {
+        let (mut left, mut result) = operand(self)?;
+        while let Some((symbol, operator)) = self.eat_operator(operators) {
+            let (right, right_type) = operand(self)?;
+            self.check(symbol, result, Type::Number);
+            self.check(symbol, right_type, Type::Number);
+            left = Expression::Binary(Box::new(left), operator, Box::new(right));
+            result = Type::Number;
+        }
+        Ok((left, result))
+    }
+
+    fn product(&mut self) -> Parsed {
+        self.arithmetic(&PRODUCTS, Parser::unary)
+    }
+
+    fn sum(&mut self) -> Parsed {
+        self.arithmetic(&SUMS, Parser::product)
+    }
+
+    fn comparison(&mut self) -> Parsed {
+        let (left, left_type) = self.sum()?;
+        let (symbol, operator) = match self.eat_operator(&COMPARISONS) {
+            Some(found) => found,
+            None => return Ok((left, left_type)),
+        };
+        let (right, right_type) = self.sum()?;
+        if let Operator::Equal | Operator::NotEqual = operator {
+            if left_type != right_type || left_type == Type::Condition {
+                self.errors.push(format!("\"{}\" compares two numbers or two names, not {} and {}.",
+                                         symbol, left_type, right_type));
+            }
+        } else {
+            self.check(symbol, left_type, Type::Number);
+            self.check(symbol, right_type, Type::Number);
+        }
+        Ok((Expression::Binary(Box::new(left), operator, Box::new(right)), Type::Condition))
+    }
+
+    fn negation(&mut self) -> Parsed {
+        if self.eat_word("not") {
+            let (value, found) = self.negation()?;
+            self.check("not", found, Type::Condition);
+            return Ok((Expression::Not(Box::new(value)), Type::Condition));
+        }
+        self.comparison()
+    }
+
+    fn logical(&mut self, word: &str, operator: Operator, operand: fn(&mut Parser<'a>) -> Parsed) -> Parsed {
+        let (mut left, mut result) = operand(self)?;
+        while self.eat_word(word) {
+            let (right, right_type) = operand(self)?;
+            self.check(word, result, Type::Condition);
+            self.check(word, right_type, Type::Condition);
+            left = Expression::Binary(Box::new(left), operator, Box::new(right));
+            result = Type::Condition;
+        }
+        Ok((left, result))
+    }
+
+    fn conjunction(&mut self) -> Parsed {
+        self.logical("and", Operator::And, Parser::negation)
+    }
+
+    fn expression(&mut self) -> Parsed {
+        self.logical("or", Operator::Or, Parser::conjunction)
+    }
+}
+
+/// What a placeholder is, as far as the structure of the text goes.
+enum Tag {
+    If(Expression),
+    Else,
+    End,
+    Node(Node),
+}
+
+fn parse_tag(parser: &mut Parser) -> Result<Tag, String> {
+    let source = format!("${{{}}}", parser.placeholder);
+    let tag = if parser.eat_word("if") {
+        let (condition, found) = parser.expression()?;
+        if found != Type::Condition {
+            parser.errors.push(format!("The condition of \"{}\" should compare something, like \"if stat.HP < 3\".",
+                                       source));
+        }
+        Tag::If(condition)
+    } else if parser.eat_word("else") {
+        Tag::Else
+    } else if parser.eat_word("end") {
+        Tag::End
+    } else if parser.eat_word("plural") {
+        let (count, found) = parser.expression()?;
+        if found != Type::Number {
+            parser.errors.push(format!("\"{}\" should count a number, not {}.", source, found));
+        }
+        let one = parser.string()?;
+        let many = parser.string()?;
+        Tag::Node(Node::Plural { count, one, many, source })
+    } else {
+        let (expression, found) = parser.expression()?;
+        if found == Type::Condition {
+            parser.errors.push(format!("\"{}\" is a condition, which can't be shown. \
+                                        Did you mean \"${{if ...}}\"?", source));
+        }
+        Tag::Node(Node::Show(expression, source))
+    };
+    match parser.rest() {
+        "" => Ok(tag),
+        _ => Err(format!("didn't expect {}", parser.found())),
+    }
+}
+
+/// An `${if}` that's still open, with the placeholder as written.
+struct Branch {
+    source: String,
+    condition: Expression,
+    then: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+/// Adds text as written, with its markup, where the next part of the text goes.
+/// Keeps track of the bold and underlined text that's open, in the order it's written.
+fn push_literal(literal: &mut String, written: &mut Style, branches: &mut [Branch], nodes: &mut Vec<Node>) {
+    let nodes = current(branches, nodes);
+    for piece in markup::parse(literal) {
+        nodes.push(match piece {
+            Piece::Text(text) => Node::Text(text),
+            Piece::Mark(mark) => {
+                mark.apply(written);
+                Node::Mark(mark)
+            }
+        });
+    }
+    literal.clear();
+}
+
+/// Where the next part of the text goes.
+fn current<'a>(branches: &'a mut [Branch], nodes: &'a mut Vec<Node>) -> &'a mut Vec<Node> {
+    match branches.last_mut() {
+        Some(Branch { otherwise: Some(otherwise), .. }) => otherwise,
+        Some(Branch { then, .. }) => then,
+        None => nodes,
+    }
+}
+
+/// Compiles a text of the game. Stats and items are looked up by their positions and names in the game,
+/// which can be post processed or not, as post processing keeps them where they are.
+pub fn compile(text: &str, game: &GameState) -> Result<Template, Vec<String>> {
+    let mut nodes = Vec::new();
+    let mut branches: Vec<Branch> = Vec::new();
+    let mut errors = Vec::new();
+    let mut literal = String::new();
+    let mut written = Style::default();
+    let mut rest = text;
+    while let Some(dollar) = rest.find('$') {
+        literal.push_str(&rest[..dollar]);
+        rest = &rest[dollar..];
+        if !rest.starts_with("${") {
+            // Either `$$` or a lone `$`, both shown as a single one.
+            literal.push('$');
+            rest = &rest[if rest.starts_with("$$") { 2 } else { 1 }..];
+            continue;
+        }
+        let end = match placeholder_end(&rest[2..]) {
+            Some(end) => end + 2,
+            None => {
+                errors.push(format!("The placeholder \"{}\" is never closed with \"}}\".", rest));
+                return Err(errors);
+            }
+        };
+        let placeholder = &rest[2..end];
+        rest = &rest[end + 1..];
+        push_literal(&mut literal, &mut written, &mut branches, &mut nodes);
+        if placeholder.trim().is_empty() {
+            errors.push("The placeholder \"${}\" is empty.".to_string());
+            continue;
+        }
+
+        let mut parser = Parser::new(game, placeholder);
+        let tag = parse_tag(&mut parser);
+        errors.extend(parser.errors);
+        let source = format!("${{{}}}", placeholder);
+        match tag {
+            Err(message) => {
+                // The rest of the text can't be made sense of without knowing what this placeholder was.
+                errors.push(format!("In \"{}\", {}.", source, message));
+                return Err(errors);
+            }
+            Ok(Tag::If(condition)) => branches.push(Branch { source, condition, then: Vec::new(), otherwise: None }),
+            Ok(Tag::Else) => match branches.last_mut() {
+                None => errors.push("\"${else}\" should come after an \"${if ...}\".".to_string()),
+                Some(branch) if branch.otherwise.is_some() =>
+                    errors.push(format!("\"{}\" already has an \"${{else}}\".", branch.source)),
+                Some(branch) => branch.otherwise = Some(Vec::new()),
+            },
+            Ok(Tag::End) => match branches.pop() {
+                None => errors.push("\"${end}\" should come after an \"${if ...}\".".to_string()),
+                Some(Branch { condition, then, otherwise, .. }) => current(&mut branches, &mut nodes).push(
+                    Node::If { condition, then, otherwise: otherwise.unwrap_or_default() }
+                ),
+            },
+            Ok(Tag::Node(node)) => current(&mut branches, &mut nodes).push(node),
+        }
+    }
+    literal.push_str(rest);
+    push_literal(&mut literal, &mut written, &mut branches, &mut nodes);
+    for branch in branches {
+        errors.push(format!("\"{}\" is never closed with \"${{end}}\".", branch.source));
+    }
+    if written.bold {
+        errors.push("A \"*\" is never closed, write \"**\" to show an asterisk.".to_string());
+    }
+    if written.underline {
+        errors.push("A \"_\" is never closed, write \"__\" to show an underscore.".to_string());
+    }
+    if errors.is_empty() { Ok(Template { nodes }) } else { Err(errors) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game() -> GameState {
+        serde_json::from_value(serde_json::json!({
+            "name": "Test",
+            "stats": [
+                {"id": 0, "name": "Gold", "default_value": 3},
+                {"id": 1, "name": "HP", "default_value": 1},
+            ],
+            "stages": [],
+            "item_slots": ["weapon"],
+            "items": [{"id": 0, "name": "Sword", "effect": {"equippable": {"slot": "weapon"}}}],
+            "entry_stage": 0,
+            "exit_stage": 0,
+        })).unwrap()
+    }
+
+    fn render(text: &str) -> String {
+        let game = game();
+        markup::plain(&compile(text, &game).unwrap().render(&game))
+    }
+
+    fn errors(text: &str) -> Vec<String> {
+        compile(text, &game()).unwrap_err()
+    }
+
+    #[test]
+    fn shows_stats_items_and_arithmetic() {
+        assert_eq!(render("You have ${stat.Gold} gold."), "You have 3 gold.");
+        assert_eq!(render("${stat.Gold * 2 + 1} ${stat.1 - 4}"), "7 -3");
+        assert_eq!(render("${item.Sword} in ${item.Sword.slot}"), "Sword in weapon");
+        assert_eq!(render("${equipped.weapon}"), "nothing");
+    }
+
+    #[test]
+    fn shows_the_placeholder_if_it_cant_be_worked_out() {
+        assert_eq!(render("${stat.Gold / 0}"), "${stat.Gold / 0}");
+    }
+
+    #[test]
+    fn conditions_and_plurals() {
+        assert_eq!(render("${if stat.HP < 3}hurt${else}fine${end}"), "hurt");
+        assert_eq!(render("${if stat.HP >= 3}hurt${else}fine${end}"), "fine");
+        assert_eq!(render("${if stat.HP <= 1 and not stat.Gold != 3}both${end}"), "both");
+        assert_eq!(render("${plural stat.Gold \"coin\" \"coins\"}, ${plural stat.HP \"life\" \"lives\"}"),
+                   "coins, life");
+    }
+
+    #[test]
+    fn dollars() {
+        assert_eq!(render("$$5 or $5, ${stat.Gold}$$"), "$5 or $5, 3$");
+    }
+
+    #[test]
+    fn reports_unknown_names() {
+        assert_eq!(errors("${stat.Mana}"), vec!["There is no stat named \"Mana\".".to_string()]);
+        assert_eq!(errors("${equipped.head}"), vec!["There is no item slot \"head\".".to_string()]);
+    }
+
+    #[test]
+    fn reports_unclosed_placeholders_and_conditions() {
+        assert_eq!(errors("${stat.Gold"), vec!["The placeholder \"${stat.Gold\" is never closed with \"}\".".to_string()]);
+        assert_eq!(errors("${if stat.HP < 3}hurt"),
+                   vec!["\"${if stat.HP < 3}\" is never closed with \"${end}\".".to_string()]);
+        assert_eq!(errors("${end}"), vec!["\"${end}\" should come after an \"${if ...}\".".to_string()]);
+    }
+
+    #[test]
+    fn styles_text_around_placeholders() {
+        let game = game();
+        let spans = compile("*${stat.Gold} gold*, [red]${item.Sword}[/]", &game).unwrap().render(&game);
+        assert_eq!(markup::plain(&spans), "3 gold, Sword");
+        assert!(spans[0].style.bold);
+        assert_eq!(spans.last().unwrap().style.color, Some(markup::Color::Red));
+    }
+
+    #[test]
+    fn reports_unclosed_markup() {
+        assert_eq!(errors("*bold"), vec!["A \"*\" is never closed, write \"**\" to show an asterisk.".to_string()]);
+        assert_eq!(errors("snake_case"),
+                   vec!["A \"_\" is never closed, write \"__\" to show an underscore.".to_string()]);
+        assert_eq!(render("5 ** 3, snake__case"), "5 * 3, snake_case");
+    }
+
+    #[test]
+    fn reports_wrong_types() {
+        assert_eq!(errors("${stat.Gold + item.Sword}"), vec!["\"+\" works on numbers, not on a name.".to_string()]);
+        assert_eq!(errors("${if stat.Gold}x${end}").len(), 1);
+    }
+}
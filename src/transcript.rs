@@ -0,0 +1,114 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::game_components::{Stage, StageOption, StatValue};
+use crate::game_state::GameState;
+use crate::markup;
+use crate::profile;
+
+pub enum TranscriptFormat {
+    Markdown,
+    PlainText,
+}
+
+/// A log of a playthrough, meant for proofreading.
+/// All text goes through `GameState::parse_format_text`, just like on screen, but without styles.
+pub struct Transcript {
+    out: BufWriter<File>,
+    format: TranscriptFormat,
+    pub path: PathBuf,
+}
+
+/// Where the in-game transcript key writes to if no file was given.
+pub fn default_transcript_path(game_name: &str) -> PathBuf {
+    PathBuf::from(profile::game_stem(game_name) + "-transcript.md")
+}
+
+impl Transcript {
+    /// Opens the transcript file, either appending to it or starting it over.
+    /// `.md` and `.markdown` files get Markdown, everything else plain text.
+    pub fn open(path: &Path, game: &GameState, append: bool) -> Result<Transcript, String> {
+        let format = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("md") | Some("markdown") => TranscriptFormat::Markdown,
+            _ => TranscriptFormat::PlainText,
+        };
+        let file = OpenOptions::new().write(true).create(true).append(append).truncate(!append).open(path)
+            .map_err(|err| format!("Could not open \"{}\": {}", path.display(), err))?;
+        let is_new = file.metadata().map(|metadata| metadata.len() == 0).unwrap_or(true);
+        let mut transcript = Transcript {
+            out: BufWriter::new(file),
+            format,
+            path: path.to_path_buf(),
+        };
+        let name = markup::strip(game.get_name());
+        let mut title = match transcript.format {
+            TranscriptFormat::Markdown => format!("# {}\n", name),
+            TranscriptFormat::PlainText => format!("{}\n{}\n", name, "=".repeat(name.chars().count())),
+        };
+        if let Some(author) = &game.metadata.author {
+            title += &match transcript.format {
+                TranscriptFormat::Markdown => format!("*by {}*\n", author),
+                TranscriptFormat::PlainText => format!("by {}\n", author),
+            };
+        }
+        if is_new {
+            transcript.write(&title)?;
+        }
+        Ok(transcript)
+    }
+
+    fn write(&mut self, text: &str) -> Result<(), String> {
+        writeln!(self.out, "{}", text)
+            .and_then(|_| self.out.flush())
+            .map_err(|err| format!("Could not write to \"{}\": {}", self.path.display(), err))
+    }
+
+    pub fn write_stage(&mut self, stage: &Stage, game: &GameState) -> Result<(), String> {
+        let heading = match self.format {
+            TranscriptFormat::Markdown => format!("## {}\n", markup::strip(&stage.name)),
+            TranscriptFormat::PlainText => format!("=== {} ===\n", markup::strip(&stage.name)),
+        };
+        self.write(&heading)?;
+        let text: Vec<String> = stage.text.iter().map(|line| game.parse_format_text(line)).collect();
+        let separator = match self.format {
+            // Markdown joins lines of a paragraph, so each one has to end with a hard line break.
+            TranscriptFormat::Markdown => "  \n",
+            TranscriptFormat::PlainText => "\n",
+        };
+        self.write(&(text.join(separator) + "\n"))
+    }
+
+    pub fn write_choice(&mut self, option: &StageOption, game: &GameState) -> Result<(), String> {
+        let text = option.text.first().map(|line| game.parse_format_text(line)).unwrap_or_default();
+        let choice = match self.format {
+            TranscriptFormat::Markdown => format!("> **Chose:** {}\n", text),
+            TranscriptFormat::PlainText => format!("> {}\n", text),
+        };
+        self.write(&choice)
+    }
+
+    /// Writes every stat that differs from its value in `before`.
+    pub fn write_stat_changes(&mut self, before: &[StatValue], game: &GameState) -> Result<(), String> {
+        for (stat, old_value) in game.stats.iter().zip(before.iter()) {
+            if stat.value == *old_value {
+                continue;
+            }
+            let name = markup::strip(&stat.name);
+            let change = match self.format {
+                TranscriptFormat::Markdown => format!("*{}: {} → {}*\n", name, old_value, stat.value),
+                TranscriptFormat::PlainText => format!("[{}: {} -> {}]\n", name, old_value, stat.value),
+            };
+            self.write(&change)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_note(&mut self, note: &str) -> Result<(), String> {
+        let note = match self.format {
+            TranscriptFormat::Markdown => format!("*({})*\n", note),
+            TranscriptFormat::PlainText => format!("({})\n", note),
+        };
+        self.write(&note)
+    }
+}